@@ -1,5 +1,5 @@
-use rand_core::{TryRng, UnwrapErr};
-use rand::{Rng, SeedableRng};
+use rand_core::TryRng;
+use rand::Rng;
 use rand::rngs::{SysRng, ChaCha20Rng};
 use rand_esdm::{EsdmRng, EsdmRngType};
 use rand_xoshiro::Xoshiro256PlusPlus;
@@ -80,7 +80,7 @@ struct BenchmarkChaCha20 {
 impl Default for BenchmarkChaCha20 {
     fn default() -> Self {
         BenchmarkChaCha20 {
-            rng: ChaCha20Rng::from_rng(&mut UnwrapErr(SysRng::default())),
+            rng: EsdmRng::new(EsdmRngType::FullySeeded).seed_rng().unwrap(),
         }
     }
 }
@@ -101,7 +101,7 @@ struct BenchmarkXoshiro256PlusPlus {
 impl Default for BenchmarkXoshiro256PlusPlus {
     fn default() -> Self {
         BenchmarkXoshiro256PlusPlus {
-            rng: Xoshiro256PlusPlus::from_rng(&mut UnwrapErr(SysRng::default())),
+            rng: EsdmRng::new(EsdmRngType::FullySeeded).seed_rng().unwrap(),
         }
     }
 }
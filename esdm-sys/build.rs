@@ -13,6 +13,9 @@ fn main() {
         .allowlist_function("esdm_rpcc_fini_unpriv_service")
         .allowlist_function("esdm_rpcc_get_random_bytes_full")
         .allowlist_function("esdm_rpcc_get_random_bytes_pr")
+        .allowlist_function("esdm_rpcc_get_random_bytes_min")
+        .allowlist_function("esdm_rpcc_get_random_bytes")
+        .allowlist_function("esdm_rpcc_get_seed")
         .allowlist_function("esdm_rpcc_init_priv_service")
         .allowlist_function("esdm_rpcc_init_unpriv_service")
         .allowlist_function("esdm_rpcc_rnd_add_entropy")
@@ -25,6 +28,11 @@ fn main() {
         .allowlist_function("esdm_rpcc_jent_status")
         .allowlist_function("esdm_rpcc_write_data")
         .allowlist_function("esdm_rpcc_get_write_wakeup_thresh")
+        .allowlist_function("esdm_rpcc_set_write_wakeup_thresh")
+        .allowlist_function("esdm_rpcc_get_min_reseed_secs")
+        .allowlist_function("esdm_rpcc_set_min_reseed_secs")
+        .allowlist_function("esdm_rpcc_get_poolsize")
+        .allowlist_function("esdm_rpcc_get_ent_lvl")
         .generate()
         .unwrap();
     let mut bindings_path = PathBuf::from(var("OUT_DIR").unwrap());
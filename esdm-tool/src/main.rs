@@ -1,19 +1,41 @@
 use rand_core::TryRng;
 use std::{
     io::{Read, Write},
+    path::PathBuf,
     process::{Child, Command, ExitCode},
     sync::mpsc::Sender,
     time::{Duration, Instant},
 };
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use rand_esdm::{
-    EsdmNotification, EsdmRng, esdm_add_entropy, esdm_crng_reseed, esdm_get_entropy_count,
-    esdm_get_entropy_level, esdm_is_fully_seeded, esdm_jent_status_str, esdm_rng_fini,
-    esdm_rng_fini_priv, esdm_rng_init, esdm_rng_init_checked, esdm_rng_init_priv_checked,
-    esdm_status_str,
+    CancellationToken, EntropyCredit, EsdmAdmin, EsdmClient, EsdmError, EsdmRngType, EsdmSeedLevel,
+    SeedFlags,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RandomMode {
+    /// only when fully seeded
+    Full,
+    /// fresh entropy for every request
+    Pr,
+    /// once minimally seeded
+    Min,
+    /// never blocks, even before seeding
+    NonBlocking,
+}
+
+impl From<RandomMode> for EsdmRngType {
+    fn from(mode: RandomMode) -> Self {
+        match mode {
+            RandomMode::Full => EsdmRngType::FullySeeded,
+            RandomMode::Pr => EsdmRngType::PredictionResistant,
+            RandomMode::Min => EsdmRngType::MinimallySeeded,
+            RandomMode::NonBlocking => EsdmRngType::NonBlocking,
+        }
+    }
+}
+
 #[derive(Debug, Args)]
 struct GetRandomArg {
     #[arg(required = true)]
@@ -22,14 +44,38 @@ struct GetRandomArg {
     #[arg(short = 'H', long, action)]
     hex: bool,
 
+    /// shorthand for `--mode pr`
     #[arg(short = 'P', long, action)]
     pr: bool,
+
+    /// [default: the client's default mode, full]
+    #[arg(short = 'm', long, value_enum)]
+    mode: Option<RandomMode>,
+}
+
+#[derive(Debug, Args)]
+struct GetSeedArg {
+    /// buffer size in bytes, grown if ESDM needs more
+    #[arg(required = false, default_value = "4096")]
+    size: usize,
+
+    #[arg(short = 'H', long, action)]
+    hex: bool,
+
+    /// fail instead of waiting for entropy
+    #[arg(short = 'n', long, action)]
+    non_blocking: bool,
+
+    /// only return data with the full security strength of the DRNG
+    #[arg(short = 'F', long, action)]
+    fully_seeded: bool,
 }
 
 #[derive(Debug, Args)]
 struct WaitUntilSeededArg {
+    /// seconds to wait at most
     #[arg(required = false, default_value = "100")]
-    tries: usize,
+    timeout_secs: u64,
 }
 
 #[derive(Debug, Args)]
@@ -40,8 +86,9 @@ struct WaitUntilSeedingNecessaryArg {
 
 #[derive(Debug, Args)]
 struct WriteToAuxPoolArg {
+    /// bits of entropy to credit, at most 8 per byte of input
     #[arg(required = false, default_value = "0")]
-    ent_bits: usize,
+    ent_bits: u32,
 }
 
 #[derive(Debug, Subcommand)]
@@ -56,6 +103,7 @@ enum ToolCommand {
     WaitUntilSeeded(WaitUntilSeededArg),
     WaitUntilSeedingNeeded(WaitUntilSeedingNecessaryArg),
     GetRandom(GetRandomArg),
+    GetSeed(GetSeedArg),
     SeedFromOs,
     ReseedFromOs,
     StressMultiThreading,
@@ -69,75 +117,146 @@ enum ToolCommand {
 struct ToolArgs {
     #[clap(subcommand)]
     command: ToolCommand,
+
+    #[arg(
+        long,
+        global = true,
+        help = "directory of the ESDM sockets, needs the `native` feature [default: $ESDM_SOCKET_DIR or /var/run]"
+    )]
+    socket_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "TOML file with ESDM client settings [default: $ESDM_CLIENT_CONFIG]"
+    )]
+    config: Option<PathBuf>,
 }
 
-fn handle_status() -> ExitCode {
-    if !esdm_rng_init() {
+fn handle_status(client: &EsdmClient) -> ExitCode {
+    let Ok(conn) = client.connection() else {
         println!("Cannot init ESDM connection. Exiting!");
         return ExitCode::FAILURE;
-    }
+    };
 
-    if let Ok(status) = esdm_status_str() {
+    if let Ok(status) = conn.status_str() {
         print!("{status}");
     } else {
         println!("Cannot get ESDM status string. Exiting!");
-        esdm_rng_fini();
         return ExitCode::FAILURE;
     }
 
-    esdm_rng_fini();
-
     ExitCode::SUCCESS
 }
 
-fn handle_jent_status() -> ExitCode {
-    if !esdm_rng_init() {
+fn handle_jent_status(client: &EsdmClient) -> ExitCode {
+    let Ok(conn) = client.connection() else {
         println!("Cannot init ESDM connection. Exiting!");
         return ExitCode::FAILURE;
-    }
+    };
 
-    if let Ok(status) = esdm_jent_status_str() {
+    if let Ok(status) = conn.jent_status_str() {
         print!("{status}");
     } else {
         println!("Cannot get ESDM jent status string. Exiting!");
-        esdm_rng_fini();
         return ExitCode::FAILURE;
     }
 
-    esdm_rng_fini();
-
     ExitCode::SUCCESS
 }
 
-fn wait_until_seeded(arg: &WaitUntilSeededArg) -> ExitCode {
-    let mut try_counter = arg.tries;
+fn wait_until_seeded(client: &EsdmClient, arg: &WaitUntilSeededArg) -> ExitCode {
+    let deadline = Instant::now() + Duration::from_secs(arg.timeout_secs);
+    let result = client
+        .connection()
+        .and_then(|conn| conn.wait_for_seed_level(EsdmSeedLevel::FullySeeded, deadline));
 
-    while try_counter > 0 {
-        if let Some(status) = esdm_is_fully_seeded()
-            && status
-        {
+    match result {
+        Ok(_) => {
             println!("ESDM is fully seeded!");
-            return ExitCode::SUCCESS;
+            ExitCode::SUCCESS
+        }
+        Err(err @ EsdmError::NotSeeded { .. }) => {
+            println!("{err} after {}s, exiting!", arg.timeout_secs);
+            ExitCode::FAILURE
+        }
+        Err(err) if err.is_unreachable() => {
+            println!("ESDM can't be reached: {err}");
+            ExitCode::FAILURE
         }
+        Err(err) => {
+            println!("Waiting for ESDM failed: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-        println!("ESDM is still not fully seeded! Retry in 1s.");
-        try_counter -= 1;
-        std::thread::sleep(Duration::from_secs(1));
+fn get_random(client: &EsdmClient, arg: &GetRandomArg) -> ExitCode {
+    let mode = if arg.pr {
+        EsdmRngType::PredictionResistant
+    } else {
+        arg.mode.map_or(client.default_mode(), Into::into)
+    };
+    let mut buf = vec![0u8; arg.size];
+    match client
+        .rng_with_mode(mode)
+        .and_then(|mut rng| rng.fill_bytes_with_seed_level(&mut buf))
+    {
+        // keep stdout free for the random bytes
+        Ok(seed_level) if seed_level != EsdmSeedLevel::FullySeeded => {
+            eprintln!("Warning: random bytes generated while ESDM was {seed_level:?}");
+        }
+        Ok(_) => {}
+        Err(err) => {
+            eprintln!("Cannot get random bytes from ESDM: {err}");
+            return ExitCode::FAILURE;
+        }
     }
 
-    println!("ESDM can't be reached or is still not fully seeded, exiting!");
-    ExitCode::FAILURE
+    if arg.hex {
+        print!("{}", hex::encode(buf));
+    } else {
+        std::io::stdout().write_all(&buf).unwrap();
+    }
+
+    ExitCode::SUCCESS
 }
 
-fn get_random(arg: &GetRandomArg) -> ExitCode {
+fn get_seed(client: &EsdmClient, arg: &GetSeedArg) -> ExitCode {
+    let Ok(conn) = client.connection() else {
+        eprintln!("Cannot init ESDM connection. Exiting!");
+        return ExitCode::FAILURE;
+    };
+
+    let mut flags = SeedFlags::BLOCKING;
+    if arg.non_blocking {
+        flags = flags | SeedFlags::NON_BLOCKING;
+    }
+    if arg.fully_seeded {
+        flags = flags | SeedFlags::FULLY_SEEDED;
+    }
+
     let mut buf = vec![0u8; arg.size];
-    let mut rng = if arg.pr {
-        EsdmRng::new(rand_esdm::EsdmRngType::PredictionResistant)
-    } else {
-        EsdmRng::new(rand_esdm::EsdmRngType::FullySeeded)
+    let mut result = conn.get_seed(&mut buf, flags);
+    if let Err(EsdmError::BufferTooSmall { required, .. }) = result {
+        buf.resize(required, 0);
+        result = conn.get_seed(&mut buf, flags);
+    }
+
+    let info = match result {
+        Ok(info) => info,
+        Err(err) => {
+            eprintln!("Cannot get seed from ESDM: {err}");
+            return ExitCode::FAILURE;
+        }
     };
-    rng.try_fill_bytes(&mut buf).unwrap();
+    buf.truncate(info.len);
 
+    // keep stdout free for the seed data
+    eprintln!(
+        "Seed: {} Byte, accounted with {} Bit of entropy.",
+        info.len, info.entropy_bits
+    );
     if arg.hex {
         print!("{}", hex::encode(buf));
     } else {
@@ -147,8 +266,8 @@ fn get_random(arg: &GetRandomArg) -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn get_entropy_level() -> ExitCode {
-    if let Some(entropy_level) = esdm_get_entropy_level() {
+fn get_entropy_level(client: &EsdmClient) -> ExitCode {
+    if let Ok(entropy_level) = client.connection().and_then(|conn| conn.entropy_level()) {
         println!("Entropy level: {entropy_level}");
         ExitCode::SUCCESS
     } else {
@@ -156,47 +275,62 @@ fn get_entropy_level() -> ExitCode {
     }
 }
 
-fn get_entropy_count() -> ExitCode {
-    esdm_rng_init_checked();
-    let cnt = esdm_get_entropy_count().unwrap();
-    println!("Entropy count: {cnt}");
-    esdm_rng_fini();
-
-    ExitCode::SUCCESS
+fn get_entropy_count(client: &EsdmClient) -> ExitCode {
+    match client.connection().and_then(|conn| conn.entropy_count()) {
+        Ok(cnt) => {
+            println!("Entropy count: {cnt}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Cannot get entropy count: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn write_to_aux_pool(arg: &WriteToAuxPoolArg) -> ExitCode {
-    esdm_rng_init_checked();
-    esdm_rng_init_priv_checked();
+/// privileged handle, reports missing privileges
+fn admin(client: &EsdmClient) -> Option<EsdmAdmin> {
+    match client.admin() {
+        Ok(admin) => Some(admin),
+        Err(err) => {
+            eprintln!("Cannot use the privileged ESDM service: {err}");
+            None
+        }
+    }
+}
 
-    let mut exit_status = ExitCode::SUCCESS;
+fn write_to_aux_pool(client: &EsdmClient, arg: &WriteToAuxPoolArg) -> ExitCode {
+    let Some(admin) = admin(client) else {
+        return ExitCode::FAILURE;
+    };
 
-    let mut stdin = std::io::stdin();
     let mut buf = vec![];
-    if let Ok(size) = stdin.read_to_end(&mut buf) {
-        if esdm_add_entropy(&buf, u32::try_from(arg.ent_bits).unwrap()).is_err() {
-            exit_status = ExitCode::FAILURE;
-            eprintln!("Failed to seed ESDM, maybe root privileges missing?");
-        } else {
+    if let Err(err) = std::io::stdin().read_to_end(&mut buf) {
+        eprintln!("Cannot read input: {err}");
+        return ExitCode::FAILURE;
+    }
+
+    match EntropyCredit::new(&buf, arg.ent_bits).and_then(|credit| admin.add_entropy(credit)) {
+        Ok(()) => {
             println!(
-                "Added {size} Byte input to ESDM Auxiliary Pool, accounted with {} Bit of entropy.",
+                "Added {} Byte input to ESDM Auxiliary Pool, accounted with {} Bit of entropy.",
+                buf.len(),
                 arg.ent_bits
             );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Failed to seed ESDM: {err}");
+            ExitCode::FAILURE
         }
-    } else {
-        println!("Seeding ESDM Aux Pool failed!");
     }
-
-    esdm_rng_fini_priv();
-    esdm_rng_fini();
-
-    exit_status
 }
 
-fn is_fully_seeded() -> ExitCode {
-    if let Some(status) = esdm_is_fully_seeded()
-        && status
-    {
+fn is_fully_seeded(client: &EsdmClient) -> ExitCode {
+    if matches!(
+        client.connection().and_then(|conn| conn.is_fully_seeded()),
+        Ok(true)
+    ) {
         println!("ESDM is fully seeded!");
         return ExitCode::SUCCESS;
     }
@@ -205,90 +339,122 @@ fn is_fully_seeded() -> ExitCode {
     ExitCode::FAILURE
 }
 
-fn crng_reseed() -> ExitCode {
-    esdm_rng_init_priv_checked();
-    let ok = esdm_crng_reseed().is_ok();
-    esdm_rng_fini_priv();
+fn crng_reseed(client: &EsdmClient) -> ExitCode {
+    let Some(admin) = admin(client) else {
+        return ExitCode::FAILURE;
+    };
 
-    if ok {
-        ExitCode::SUCCESS
-    } else {
-        println!("CRNG reseed failed. Missing root privileges?");
-        ExitCode::FAILURE
+    match admin.reseed_crng() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("CRNG reseed failed: {err}");
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn wait_until_seeding_necessary(arg: &WaitUntilSeedingNecessaryArg) -> ExitCode {
-    esdm_rng_init_checked();
-
-    let mut notifier = EsdmNotification::new();
-
-    let ret = if notifier
-        .wait_for_entropy_needed_timeout(Duration::from_secs(arg.timeout_secs))
-        .is_ok()
-    {
-        ExitCode::SUCCESS
-    } else {
-        ExitCode::FAILURE
+fn wait_until_seeding_necessary(
+    client: &EsdmClient,
+    arg: &WaitUntilSeedingNecessaryArg,
+) -> ExitCode {
+    let notifier = match client.notification() {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            eprintln!("Cannot init ESDM aux client: {err}");
+            return ExitCode::FAILURE;
+        }
     };
 
-    esdm_rng_fini();
-
-    ret
+    match notifier.wait_for_entropy_needed_timeout(Duration::from_secs(arg.timeout_secs)) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(EsdmError::Timeout { .. }) => {
+            eprintln!("ESDM did not need entropy within {}s", arg.timeout_secs);
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("Waiting for ESDM failed: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn seed_from_os() -> ExitCode {
-    esdm_rng_init_priv_checked();
+fn seed_from_os(client: &EsdmClient) -> ExitCode {
+    let Some(admin) = admin(client) else {
+        return ExitCode::FAILURE;
+    };
 
-    let mut exit_status = ExitCode::SUCCESS;
     // get 64 Byte from OS
     let mut buf = vec![0u8; 64];
     if getrandom::fill(&mut buf).is_err() {
-        esdm_rng_fini_priv();
         return ExitCode::FAILURE;
     }
 
-    if esdm_add_entropy(&buf, u32::try_from(buf.len() * 8).unwrap()).is_err() {
-        exit_status = ExitCode::FAILURE;
-        eprintln!("Failed to seed ESDM, maybe root privileges missing?");
-    } else {
-        println!(
-            "Added {} Byte input to ESDM Auxiliary Pool, accounted with {} Bit of entropy.",
-            buf.len(),
-            buf.len() * 8
-        );
+    let credit = EntropyCredit::full(&buf);
+    if let Err(err) = admin.add_entropy(credit) {
+        eprintln!("Failed to seed ESDM: {err}");
+        return ExitCode::FAILURE;
     }
+    println!(
+        "Added {} Byte input to ESDM Auxiliary Pool, accounted with {} Bit of entropy.",
+        buf.len(),
+        credit.bits()
+    );
 
-    esdm_rng_fini_priv();
-
-    exit_status
+    ExitCode::SUCCESS
 }
 
-fn reseed_from_os() -> ExitCode {
+/// how long `reseed-from-os` waits for a need-entropy event, before it reseeds anyway
+const RESEED_WAIT_TIMEOUT: Duration = Duration::from_secs(100);
+
+fn reseed_from_os(client: &EsdmClient, token: &CancellationToken) -> ExitCode {
+    let notifier = match client.notification() {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            eprintln!("Cannot init ESDM aux client: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
     let start = Instant::now();
     for i in 0..100_000 {
-        let arg = WaitUntilSeedingNecessaryArg { timeout_secs: 100 };
-        wait_until_seeding_necessary(&arg);
+        match notifier.wait_for_entropy_needed_timeout(RESEED_WAIT_TIMEOUT) {
+            Ok(_) | Err(EsdmError::Timeout { .. }) => {}
+            Err(EsdmError::Cancelled { .. }) => break,
+            Err(err) => {
+                eprintln!("Waiting for ESDM failed: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+        if token.is_cancelled() {
+            break;
+        }
         let elapsed = start.elapsed();
         println!(
             "Wakeup {i} after {} secs: need entropy",
             elapsed.as_secs_f64()
         );
 
-        let _ = seed_from_os();
+        let exit_code = seed_from_os(client);
+        if exit_code != ExitCode::SUCCESS {
+            return exit_code;
+        }
         let elapsed = start.elapsed();
         println!("Reseed {i} after {} secs: reseeded", elapsed.as_secs_f64());
     }
 
-    ExitCode::SUCCESS
+    if token.is_cancelled() {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-fn stress_multi_threading(num_threads: Option<usize>) -> ExitCode {
+fn stress_multi_threading(client: &EsdmClient, num_threads: Option<usize>) -> ExitCode {
     use std::sync::mpsc;
 
     let mut threads = vec![];
 
-    let rng = &mut EsdmRng::new(rand_esdm::EsdmRngType::FullySeeded);
+    let rng = &mut client.rng().unwrap();
     let _ = rng.try_next_u64().unwrap();
     println!("Got bytes on a single core! Start multi-core stress test!");
 
@@ -304,8 +470,9 @@ fn stress_multi_threading(num_threads: Option<usize>) -> ExitCode {
     for i in 0..cores {
         println!("Start thread {i}");
         let mut tx1 = tx.clone();
+        let client = *client;
         threads.push(std::thread::spawn(move || {
-            stress_one_core(&mut tx1);
+            stress_one_core(&client, &mut tx1);
         }));
     }
 
@@ -320,8 +487,8 @@ fn stress_multi_threading(num_threads: Option<usize>) -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn stress_one_core(tx: &mut Sender<String>) {
-    let mut rng = rand_esdm::EsdmRng::new(rand_esdm::EsdmRngType::FullySeeded);
+fn stress_one_core(client: &EsdmClient, tx: &mut Sender<String>) {
+    let mut rng = client.rng().unwrap();
     let mut mean_duration = 0.0;
     let alpha = 0.2;
     let mut i: u64 = 0;
@@ -344,20 +511,27 @@ fn stress_one_core(tx: &mut Sender<String>) {
     }
 }
 
-fn stress_delay() -> ExitCode {
-    stress_multi_threading(Some(1))
+fn stress_delay(client: &EsdmClient) -> ExitCode {
+    stress_multi_threading(client, Some(1))
 }
 
-fn measure_speed() -> ExitCode {
+fn measure_speed(client: &EsdmClient) -> ExitCode {
     use std::time::Instant;
 
     let sizes = cute::c![1 << x, for x in 0..12];
 
     for m in ["Fully Seeded", "Prediction Resistant"] {
-        let mut rng = if m == "Fully Seeded" {
-            EsdmRng::new(rand_esdm::EsdmRngType::FullySeeded)
+        let rng_type = if m == "Fully Seeded" {
+            EsdmRngType::FullySeeded
         } else {
-            EsdmRng::new(rand_esdm::EsdmRngType::PredictionResistant)
+            EsdmRngType::PredictionResistant
+        };
+        let mut rng = match client.rng_with_mode(rng_type) {
+            Ok(rng) => rng,
+            Err(err) => {
+                eprintln!("Cannot init ESDM connection: {err}");
+                return ExitCode::FAILURE;
+            }
         };
 
         let iterations = if m == "Fully Seeded" { 20000 } else { 100 };
@@ -369,7 +543,10 @@ fn measure_speed() -> ExitCode {
             let now = Instant::now();
 
             for _ in 0..iterations {
-                rng.try_fill_bytes(&mut buf).unwrap();
+                if let Err(err) = rng.try_fill_bytes(&mut buf) {
+                    eprintln!("Getting random bytes failed: {err}");
+                    return ExitCode::FAILURE;
+                }
             }
 
             let elapsed = now.elapsed();
@@ -399,12 +576,11 @@ fn measure_speed() -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn stress_multi_process() -> ExitCode {
+fn stress_multi_process(client: &EsdmClient, args: &ToolArgs) -> ExitCode {
     use std::env;
 
     // test if fds are leaking
-    esdm_rng_init_checked();
-    let mut rng = EsdmRng::new(rand_esdm::EsdmRngType::FullySeeded);
+    let mut rng = client.rng().unwrap();
     for _ in 0..100 {
         let r = rng.try_next_u64().unwrap();
         println!("rnd: {r}");
@@ -419,10 +595,15 @@ fn stress_multi_process() -> ExitCode {
         Ok(exe_path) => {
             println!("Path of this executable is: {}", exe_path.display());
             for _ in 0..cores {
-                let p = Command::new(&exe_path)
-                    .args(["stress-delay"])
-                    .spawn()
-                    .unwrap();
+                let mut cmd = Command::new(&exe_path);
+                cmd.arg("stress-delay");
+                if let Some(config) = &args.config {
+                    cmd.arg("--config").arg(config);
+                }
+                if let Some(dir) = &args.socket_dir {
+                    cmd.arg("--socket-dir").arg(dir);
+                }
+                let p = cmd.spawn().unwrap();
                 processes.push(p);
             }
         }
@@ -436,25 +617,223 @@ fn stress_multi_process() -> ExitCode {
     ExitCode::SUCCESS
 }
 
+/// time a command gets to wind down after Ctrl-C, before the process exits
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// exit code of a command ended by SIGINT
+const EXIT_INTERRUPTED: u8 = 130;
+
+/// cancels the ESDM calls of the command on Ctrl-C, exits if it does not end in time
+fn set_interrupt_handler(token: CancellationToken) {
+    let result = ctrlc::set_handler(move || {
+        token.cancel();
+        std::thread::sleep(INTERRUPT_GRACE_PERIOD);
+        std::process::exit(EXIT_INTERRUPTED.into());
+    });
+    if let Err(err) = result {
+        eprintln!("Cannot handle Ctrl-C, ESDM calls will not be cancelled: {err}");
+    }
+}
+
+/// client settings from the configuration file, the environment and the command line, in
+/// increasing precedence
+fn build_client(args: &ToolArgs) -> Result<EsdmClient, EsdmError> {
+    let mut builder = EsdmClient::builder();
+    if let Some(config) = &args.config {
+        builder = builder.load_file(config)?;
+    }
+    builder = builder.load_env()?;
+    if let Some(dir) = &args.socket_dir {
+        // the C client libraries of the `ffi` backend only use their compiled-in paths
+        #[cfg(feature = "native")]
+        {
+            builder = builder.socket_dir(dir);
+        }
+        #[cfg(not(feature = "native"))]
+        {
+            let _ = dir;
+            return Err(EsdmError::InvalidConfig {
+                setting: "--socket-dir".to_string(),
+                reason: "custom socket paths need esdm-tool built with the `native` feature"
+                    .to_string(),
+            });
+        }
+    }
+
+    builder.build()
+}
+
 fn main() -> ExitCode {
     let args = ToolArgs::parse();
 
-    match args.command {
-        ToolCommand::IsFullySeeded => is_fully_seeded(),
-        ToolCommand::Status => handle_status(),
-        ToolCommand::JentStatus => handle_jent_status(),
-        ToolCommand::WaitUntilSeeded(arg) => wait_until_seeded(&arg),
-        ToolCommand::GetRandom(arg) => get_random(&arg),
-        ToolCommand::EntropyLevel => get_entropy_level(),
-        ToolCommand::EntropyCount => get_entropy_count(),
-        ToolCommand::WriteToAuxPool(arg) => write_to_aux_pool(&arg),
-        ToolCommand::CrngReseed => crng_reseed(),
-        ToolCommand::WaitUntilSeedingNeeded(arg) => wait_until_seeding_necessary(&arg),
-        ToolCommand::SeedFromOs => seed_from_os(),
-        ToolCommand::StressDelay => stress_delay(),
-        ToolCommand::StressMultiThreading => stress_multi_threading(None),
-        ToolCommand::ReseedFromOs => reseed_from_os(),
-        ToolCommand::Speed => measure_speed(),
-        ToolCommand::StressMultiProcess => stress_multi_process(),
+    let client = match build_client(&args) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Cannot configure ESDM client: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let token = CancellationToken::new();
+    set_interrupt_handler(token.clone());
+
+    let exit_code = token.run(|| run_command(&client, &args, &token));
+    if token.is_cancelled() {
+        eprintln!("Interrupted");
+        return ExitCode::from(EXIT_INTERRUPTED);
+    }
+
+    exit_code
+}
+
+fn run_command(client: &EsdmClient, args: &ToolArgs, token: &CancellationToken) -> ExitCode {
+    match &args.command {
+        ToolCommand::IsFullySeeded => is_fully_seeded(client),
+        ToolCommand::Status => handle_status(client),
+        ToolCommand::JentStatus => handle_jent_status(client),
+        ToolCommand::WaitUntilSeeded(arg) => wait_until_seeded(client, arg),
+        ToolCommand::GetRandom(arg) => get_random(client, arg),
+        ToolCommand::GetSeed(arg) => get_seed(client, arg),
+        ToolCommand::EntropyLevel => get_entropy_level(client),
+        ToolCommand::EntropyCount => get_entropy_count(client),
+        ToolCommand::WriteToAuxPool(arg) => write_to_aux_pool(client, arg),
+        ToolCommand::CrngReseed => crng_reseed(client),
+        ToolCommand::WaitUntilSeedingNeeded(arg) => wait_until_seeding_necessary(client, arg),
+        ToolCommand::SeedFromOs => seed_from_os(client),
+        ToolCommand::StressDelay => stress_delay(client),
+        ToolCommand::StressMultiThreading => stress_multi_threading(client, None),
+        ToolCommand::ReseedFromOs => reseed_from_os(client, token),
+        ToolCommand::Speed => measure_speed(client),
+        ToolCommand::StressMultiProcess => stress_multi_process(client, args),
+    }
+}
+
+// hermetic tests of the subcommands against the in-process mock ESDM server
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use rand_esdm::EsdmOperation;
+    use rand_esdm::mock::MockEsdmServer;
+
+    #[test]
+    fn test_status_commands() {
+        let _server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+
+        assert_eq!(handle_status(&client), ExitCode::SUCCESS);
+        assert_eq!(handle_jent_status(&client), ExitCode::SUCCESS);
+        assert_eq!(get_entropy_level(&client), ExitCode::SUCCESS);
+        assert_eq!(get_entropy_count(&client), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn test_seeding_state() {
+        let server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+
+        assert_eq!(is_fully_seeded(&client), ExitCode::SUCCESS);
+        assert_eq!(
+            wait_until_seeded(&client, &WaitUntilSeededArg { timeout_secs: 1 }),
+            ExitCode::SUCCESS
+        );
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        assert_eq!(is_fully_seeded(&client), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_get_random() {
+        let server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+
+        let mut arg = GetRandomArg {
+            size: 32,
+            hex: true,
+            pr: true,
+            mode: None,
+        };
+        assert_eq!(get_random(&client, &arg), ExitCode::SUCCESS);
+
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+        assert_eq!(get_random(&client, &arg), ExitCode::FAILURE);
+
+        arg.pr = false;
+        arg.mode = Some(RandomMode::NonBlocking);
+        assert_eq!(get_random(&client, &arg), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn test_get_seed() {
+        let server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+
+        // too small buffers are grown
+        let mut arg = GetSeedArg {
+            size: 16,
+            hex: true,
+            non_blocking: true,
+            fully_seeded: true,
+        };
+        assert_eq!(get_seed(&client, &arg), ExitCode::SUCCESS);
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        assert_eq!(get_seed(&client, &arg), ExitCode::FAILURE);
+        arg.fully_seeded = false;
+        assert_eq!(get_seed(&client, &arg), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn test_privileged_commands() {
+        let server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+
+        // the privilege pre-check looks at the test process, not at the mock server
+        if EsdmAdmin::new().is_err() {
+            assert_eq!(crng_reseed(&client), ExitCode::FAILURE);
+            return;
+        }
+
+        assert_eq!(crng_reseed(&client), ExitCode::SUCCESS);
+        assert_eq!(seed_from_os(&client), ExitCode::SUCCESS);
+
+        server.set_privileged(false);
+        assert_eq!(crng_reseed(&client), ExitCode::FAILURE);
+        assert_eq!(seed_from_os(&client), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_socket_dir() {
+        let server = MockEsdmServer::start().unwrap();
+        let dir = server.socket_dir().to_path_buf();
+        let args = ToolArgs::try_parse_from([
+            "esdm-tool".as_ref(),
+            "status".as_ref(),
+            "--socket-dir".as_ref(),
+            dir.as_os_str(),
+        ])
+        .unwrap();
+
+        let client = build_client(&args).unwrap();
+        assert_eq!(
+            rand_esdm::EsdmClientConfig::current(),
+            rand_esdm::EsdmClientConfig::new().with_socket_dir(&dir)
+        );
+        assert_eq!(handle_status(&client), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn test_reseed_from_os_interrupted() {
+        let server = MockEsdmServer::start().unwrap();
+        let client = EsdmClient::default();
+        server.set_entropy_count(0);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let start = Instant::now();
+        let exit_code = token.run(|| reseed_from_os(&client, &token));
+
+        assert_eq!(exit_code, ExitCode::from(EXIT_INTERRUPTED));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.calls(EsdmOperation::AddEntropy), 0);
     }
 }
@@ -0,0 +1,339 @@
+//! Privileged ESDM administration, see [`EsdmAdmin`]
+
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::{
+    EsdmConnection, EsdmError, EsdmOperation, EsdmPrivConnection, MissingPrivilege, backend,
+};
+
+/// bit of `CAP_SYS_ADMIN` in the capability sets of `/proc/<pid>/status`
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Data for the auxiliary pool and the bits of entropy credited for it
+///
+/// At most 8 bits are credited per byte of data.
+#[derive(Clone, Copy)]
+pub struct EntropyCredit<'a> {
+    data: &'a [u8],
+    bits: u32,
+}
+
+impl fmt::Debug for EntropyCredit<'_> {
+    // the data is (supposedly) secret
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EntropyCredit")
+            .field("bytes", &self.data.len())
+            .field("bits", &self.bits)
+            .finish()
+    }
+}
+
+impl<'a> EntropyCredit<'a> {
+    /// credits `bits` of entropy for `data`, fails with
+    /// [`EsdmError::InvalidEntropyCredit`] for more than 8 bits per byte
+    pub fn new(data: &'a [u8], bits: u32) -> Result<Self, EsdmError> {
+        if u64::from(bits) > Self::max_bits(data) {
+            return Err(EsdmError::InvalidEntropyCredit {
+                bits,
+                bytes: data.len(),
+            });
+        }
+        Ok(EntropyCredit { data, bits })
+    }
+
+    /// credits 8 bits per byte of `data`, e.g. for the output of a full
+    /// entropy source
+    #[must_use]
+    pub fn full(data: &'a [u8]) -> Self {
+        EntropyCredit {
+            data,
+            bits: u32::try_from(Self::max_bits(data)).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// mixes `data` into the pool without crediting entropy
+    #[must_use]
+    pub fn none(data: &'a [u8]) -> Self {
+        EntropyCredit { data, bits: 0 }
+    }
+
+    fn max_bits(data: &[u8]) -> u64 {
+        u64::try_from(data.len())
+            .unwrap_or(u64::MAX)
+            .saturating_mul(8)
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    #[must_use]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+/// true or false, if the `CapEff` line of a `/proc/<pid>/status` text lists
+/// `CAP_SYS_ADMIN`, `None` without such a line
+fn has_cap_sys_admin(status: &str) -> Option<bool> {
+    let caps = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    let caps = u64::from_str_radix(caps.trim(), 16).ok()?;
+    Some(caps & (1 << CAP_SYS_ADMIN) != 0)
+}
+
+/// the `/proc/self/status` text of this process, `None` without procfs
+fn proc_self_status() -> Option<String> {
+    std::fs::read_to_string("/proc/self/status").ok()
+}
+
+/// true, if a process with the `/proc/<pid>/status` text `status` holds
+/// `CAP_SYS_ADMIN`
+fn process_has_cap_sys_admin(status: Option<&str>) -> bool {
+    status
+        .and_then(has_cap_sys_admin)
+        // without procfs, assume root holds all capabilities
+        .unwrap_or_else(|| unsafe { libc::geteuid() } == 0)
+}
+
+/// checks the effective read and write access to the socket at `path`
+fn check_socket_access(path: &Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let ret = unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::R_OK | libc::W_OK,
+            libc::AT_EACCESS,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// checks the privileges esdm-server requires of privileged callers, for a
+/// process with the `/proc/<pid>/status` text `status`
+fn check_privileges(status: Option<&str>) -> Result<(), EsdmError> {
+    if !process_has_cap_sys_admin(status) {
+        return Err(EsdmError::NotPrivileged {
+            op: EsdmOperation::InitPrivService,
+            missing: MissingPrivilege::CapSysAdmin,
+        });
+    }
+
+    let path = backend::priv_socket_path();
+    match check_socket_access(&path) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            Err(EsdmError::NotPrivileged {
+                op: EsdmOperation::InitPrivService,
+                missing: MissingPrivilege::SocketAccess { path },
+            })
+        }
+        // a missing socket is reported by the connection attempt
+        _ => Ok(()),
+    }
+}
+
+/// turns a refusal of the privileged service into [`EsdmError::NotPrivileged`]
+fn refused(err: EsdmError) -> EsdmError {
+    let op = err.operation();
+    match (err.code().map(i32::saturating_abs), op) {
+        (Some(libc::EACCES), EsdmOperation::InitPrivService) => EsdmError::NotPrivileged {
+            op,
+            missing: MissingPrivilege::SocketAccess {
+                path: backend::priv_socket_path(),
+            },
+        },
+        (Some(libc::EPERM | libc::EACCES), _) if err.is_privileged() => EsdmError::NotPrivileged {
+            op,
+            missing: MissingPrivilege::CapSysAdmin,
+        },
+        _ => err,
+    }
+}
+
+/// Handle on the privileged ESDM service, created after checking privileges
+///
+/// esdm-server serves the privileged RPC service (entropy accounting, pool
+/// management, reseeding) only to callers with `CAP_SYS_ADMIN`, which also
+/// need read and write access to its socket. [`EsdmAdmin::new`] checks both
+/// before connecting, so that missing privileges are reported as
+/// [`EsdmError::NotPrivileged`] naming the missing one, instead of a generic
+/// `EPERM` of the first privileged call. Refusals of esdm-server itself are
+/// reported the same way.
+///
+/// Entropy is added as an [`EntropyCredit`], which never credits more than
+/// 8 bits per byte of data. The handle is also usable as [`EsdmConnection`].
+#[derive(Debug, Clone)]
+pub struct EsdmAdmin {
+    conn: EsdmPrivConnection,
+}
+
+impl EsdmAdmin {
+    /// checks the privileges of the process and connects to the privileged service
+    pub fn new() -> Result<Self, EsdmError> {
+        Self::connect(EsdmPrivConnection::new)
+    }
+
+    pub(crate) fn connect(
+        connect: impl FnOnce() -> Result<EsdmPrivConnection, EsdmError>,
+    ) -> Result<Self, EsdmError> {
+        Self::connect_as(proc_self_status().as_deref(), connect)
+    }
+
+    /// connects after checking the privileges of a process with the
+    /// `/proc/<pid>/status` text `status`
+    fn connect_as(
+        status: Option<&str>,
+        connect: impl FnOnce() -> Result<EsdmPrivConnection, EsdmError>,
+    ) -> Result<Self, EsdmError> {
+        check_privileges(status)?;
+        Ok(EsdmAdmin {
+            conn: connect().map_err(refused)?,
+        })
+    }
+
+    /// uses an established connection without checking privileges up front
+    #[must_use]
+    pub fn from_connection(conn: EsdmPrivConnection) -> Self {
+        EsdmAdmin { conn }
+    }
+
+    /// the privileged connection held by this handle
+    #[must_use]
+    pub fn connection(&self) -> &EsdmPrivConnection {
+        &self.conn
+    }
+
+    /// adds the data of `credit` to the auxiliary pool, crediting its bits of entropy
+    pub fn add_entropy(&self, credit: EntropyCredit<'_>) -> Result<(), EsdmError> {
+        self.conn.add_entropy(credit).map_err(refused)
+    }
+
+    /// increases the entropy count of the auxiliary pool by `bits`
+    pub fn add_to_entropy_count(&self, bits: u32) -> Result<(), EsdmError> {
+        self.conn.add_to_entropy_count(bits).map_err(refused)
+    }
+
+    /// forces a reseed of the DRNG
+    pub fn reseed_crng(&self) -> Result<(), EsdmError> {
+        self.conn.reseed_crng().map_err(refused)
+    }
+
+    /// clears the entropy count of the auxiliary pool
+    pub fn clear_pool(&self) -> Result<(), EsdmError> {
+        self.conn.clear_pool().map_err(refused)
+    }
+
+    /// sets the entropy count in bits below which writers of entropy are woken up
+    pub fn set_write_wakeup_thresh(&self, write_wakeup_thresh: u32) -> Result<(), EsdmError> {
+        self.conn
+            .set_write_wakeup_thresh(write_wakeup_thresh)
+            .map_err(refused)
+    }
+
+    /// sets the minimum number of seconds between two reseeds of the ESDM DRNG
+    pub fn set_min_reseed_secs(&self, min_reseed_secs: u32) -> Result<(), EsdmError> {
+        self.conn
+            .set_min_reseed_secs(min_reseed_secs)
+            .map_err(refused)
+    }
+}
+
+impl Deref for EsdmAdmin {
+    type Target = EsdmConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.unprivileged()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entropy_credit() {
+        let data = [0u8; 16];
+        assert_eq!(EntropyCredit::new(&data, 128).unwrap().bits(), 128);
+        assert_eq!(EntropyCredit::full(&data).bits(), 128);
+        assert_eq!(EntropyCredit::none(&data).bits(), 0);
+
+        let err = EntropyCredit::new(&data, 129).unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::InvalidEntropyCredit {
+                bits: 129,
+                bytes: 16
+            }
+        ));
+        assert_eq!(err.errno(), Some(libc::EINVAL));
+        assert!(EntropyCredit::new(&[], 1).is_err());
+    }
+
+    #[test]
+    fn test_has_cap_sys_admin() {
+        let status = "Name:\tesdm-tool\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        assert_eq!(has_cap_sys_admin(status), Some(true));
+        assert_eq!(
+            has_cap_sys_admin("CapEff:\t0000000000000400\n"),
+            Some(false)
+        );
+        assert_eq!(has_cap_sys_admin("Name:\tesdm-tool\n"), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_admin() {
+        use crate::mock::MockEsdmServer;
+
+        // the test process may lack CAP_SYS_ADMIN, the mock server does not care
+        const PRIVILEGED: &str = "CapEff:\t0000000000200000\n";
+        const UNPRIVILEGED: &str = "CapEff:\t0000000000000000\n";
+
+        let server = MockEsdmServer::start().unwrap();
+
+        let err = EsdmAdmin::connect_as(Some(UNPRIVILEGED), EsdmPrivConnection::new).unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::NotPrivileged {
+                op: EsdmOperation::InitPrivService,
+                missing: MissingPrivilege::CapSysAdmin
+            }
+        ));
+
+        let admin = EsdmAdmin::connect_as(Some(PRIVILEGED), EsdmPrivConnection::new).unwrap();
+
+        admin.clear_pool().unwrap();
+        let data = [7u8; 32];
+        admin.add_entropy(EntropyCredit::full(&data)).unwrap();
+        assert_eq!(admin.entropy_count().unwrap(), 256);
+        admin.reseed_crng().unwrap();
+
+        server.set_privileged(false);
+        let err = admin.reseed_crng().unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::NotPrivileged {
+                op: EsdmOperation::ReseedCrng,
+                missing: MissingPrivilege::CapSysAdmin
+            }
+        ));
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            err.to_string(),
+            "ESDM esdm_rpcc_rnd_reseed_crng needs CAP_SYS_ADMIN"
+        );
+    }
+}
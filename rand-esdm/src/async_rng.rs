@@ -0,0 +1,233 @@
+//! tokio integration: random bytes and need-entropy events without blocking
+//!
+//! All ESDM client calls block, so they run on tokio's blocking thread pool
+//! (random bytes) or a dedicated thread (need-entropy notification) instead of
+//! a runtime worker thread.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{EsdmError, EsdmNotification, EsdmOperation, EsdmRng, EsdmRngType};
+
+/// maximum number of bytes fetched by one blocking call when used as `AsyncRead`
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// how long the notification thread waits, before checking if the stream was dropped
+const NEED_ENTROPY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// awaits a blocking task, panics of the task are propagated
+async fn join<T>(
+    handle: JoinHandle<Result<T, EsdmError>>,
+    op: EsdmOperation,
+) -> Result<T, EsdmError> {
+    match handle.await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(EsdmError::Os {
+            op,
+            source: io::Error::other(err),
+        }),
+    }
+}
+
+/// fetches `len` random bytes on the blocking thread pool
+fn spawn_fill(rng: &EsdmRng, len: usize) -> JoinHandle<Result<Zeroizing<Vec<u8>>, EsdmError>> {
+    // the clone keeps the ESDM connection alive until the blocking call returned,
+    // even if the AsyncEsdmRng is dropped in the meantime
+    let conn = rng.connection().clone();
+    let rng_type = rng.rng_type();
+
+    tokio::task::spawn_blocking(move || {
+        let mut buf = Zeroizing::new(vec![0u8; len]);
+        conn.fill_bytes(rng_type, &mut buf).map(|()| buf)
+    })
+}
+
+/// [`EsdmRng`] for async code, implements [`AsyncRead`] as endless random stream
+///
+/// Must be used from within a tokio runtime.
+pub struct AsyncEsdmRng {
+    rng: EsdmRng,
+    pending: Option<JoinHandle<Result<Zeroizing<Vec<u8>>, EsdmError>>>,
+    leftover: Zeroizing<Vec<u8>>,
+}
+
+impl AsyncEsdmRng {
+    #[must_use]
+    pub fn new(rng_type: EsdmRngType) -> Self {
+        AsyncEsdmRng::from(EsdmRng::new(rng_type))
+    }
+
+    /// fills `dst` completely with random bytes
+    pub async fn fill(&mut self, dst: &mut [u8]) -> Result<(), EsdmError> {
+        let op = self.rng.rng_type().operation();
+        let random = join(spawn_fill(&self.rng, dst.len()), op).await?;
+        dst.copy_from_slice(&random);
+
+        Ok(())
+    }
+
+    /// returns random bytes in a freshly allocated vector
+    pub async fn bytes(&mut self, len: usize) -> Result<Vec<u8>, EsdmError> {
+        let op = self.rng.rng_type().operation();
+        let mut random = join(spawn_fill(&self.rng, len), op).await?;
+        Ok(std::mem::take(&mut *random))
+    }
+
+    pub async fn next_u64(&mut self) -> Result<u64, EsdmError> {
+        let mut bytes = Zeroizing::new([0u8; 8]);
+        self.fill(bytes.as_mut_slice()).await?;
+
+        Ok(u64::from_ne_bytes(*bytes))
+    }
+}
+
+impl From<EsdmRng> for AsyncEsdmRng {
+    fn from(rng: EsdmRng) -> Self {
+        AsyncEsdmRng {
+            rng,
+            pending: None,
+            leftover: Zeroizing::new(Vec::new()),
+        }
+    }
+}
+
+impl AsyncRead for AsyncEsdmRng {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        if this.leftover.is_empty() {
+            let rng = &this.rng;
+            let pending = this
+                .pending
+                .get_or_insert_with(|| spawn_fill(rng, buf.remaining().min(READ_CHUNK_SIZE)));
+
+            let result = ready!(Pin::new(pending).poll(cx));
+            this.pending = None;
+
+            this.leftover = match result {
+                Ok(Ok(random)) => random,
+                Ok(Err(err)) => return Poll::Ready(Err(err.into())),
+                Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                Err(err) => return Poll::Ready(Err(io::Error::other(err))),
+            };
+        }
+
+        let len = buf.remaining().min(this.leftover.len());
+        buf.put_slice(&this.leftover[..len]);
+        // draining moves the rest to the front, wipe the stale tail behind it
+        let rest = this.leftover.len() - len;
+        this.leftover.copy_within(len.., 0);
+        this.leftover[rest..].zeroize();
+        this.leftover.truncate(rest);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Stream of need-entropy events, yields the ESDM entropy count of each event
+///
+/// Created by [`NeedEntropyStream::new`], waits for the events on a dedicated
+/// thread, which ends shortly after the stream is dropped.
+pub struct NeedEntropyStream {
+    rx: mpsc::Receiver<Result<u32, EsdmError>>,
+}
+
+impl NeedEntropyStream {
+    pub fn new() -> Result<Self, EsdmError> {
+        let notification = EsdmNotification::try_new()?;
+
+        let (tx, rx) = mpsc::channel(1);
+        std::thread::spawn(move || {
+            while !tx.is_closed() {
+                match notification.wait_for_entropy_needed_timeout(NEED_ENTROPY_POLL_INTERVAL) {
+                    Err(EsdmError::Timeout { .. }) => {}
+                    Ok(entropy_count) => {
+                        if tx.blocking_send(Ok(entropy_count)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        if tx.blocking_send(Err(err)).is_err() {
+                            break;
+                        }
+                        std::thread::sleep(NEED_ENTROPY_POLL_INTERVAL);
+                    }
+                }
+            }
+
+            drop(notification);
+        });
+
+        Ok(NeedEntropyStream { rx })
+    }
+
+    /// waits for the next need-entropy event
+    pub async fn recv(&mut self) -> Option<Result<u32, EsdmError>> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for NeedEntropyStream {
+    type Item = Result<u32, EsdmError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::EsdmSeedLevel;
+    use crate::mock::MockEsdmServer;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_async_fill() {
+        let server = MockEsdmServer::start().unwrap();
+        let mut rng = AsyncEsdmRng::new(EsdmRngType::FullySeeded);
+
+        let mut buf = [0u8; 32];
+        rng.fill(&mut buf).await.unwrap();
+        assert_ne!(buf, [0u8; 32]);
+
+        let mut buf = vec![0u8; 3 * READ_CHUNK_SIZE + 1];
+        rng.read_exact(&mut buf).await.unwrap();
+
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+        assert!(rng.next_u64().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_need_entropy_stream() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_write_wakeup_thresh(128);
+        server.set_entropy_count(64);
+
+        let mut stream = NeedEntropyStream::new().unwrap();
+        assert_eq!(stream.recv().await.unwrap().unwrap(), 64);
+
+        // the notification thread releases its connection shortly after the
+        // stream is dropped, wait for it before other tests use the mock server
+        drop(stream);
+        std::thread::sleep(2 * NEED_ENTROPY_POLL_INTERVAL);
+        drop(server);
+    }
+}
@@ -0,0 +1,132 @@
+//! Backend linking the ESDM C client libraries via esdm-sys
+
+use std::ffi::c_char;
+use std::path::PathBuf;
+
+use esdm_sys::esdm;
+use esdm_sys::esdm_aux;
+
+pub(crate) fn init_unpriv_service() -> i32 {
+    unsafe { esdm::esdm_rpcc_init_unpriv_service(Some(crate::cancel::rpc_interrupt)) }
+}
+
+pub(crate) fn fini_unpriv_service() {
+    unsafe { esdm::esdm_rpcc_fini_unpriv_service() };
+}
+
+pub(crate) fn init_priv_service() -> i32 {
+    unsafe { esdm::esdm_rpcc_init_priv_service(Some(crate::cancel::rpc_interrupt)) }
+}
+
+pub(crate) fn fini_priv_service() {
+    unsafe { esdm::esdm_rpcc_fini_priv_service() };
+}
+
+/// socket of the privileged service, as used by the next connection
+pub(crate) fn priv_socket_path() -> PathBuf {
+    PathBuf::from(super::PRIV_SOCKET)
+}
+
+pub(crate) fn set_max_online_nodes(nodes: u32) {
+    unsafe { esdm::esdm_rpcc_set_max_online_nodes(nodes) };
+}
+
+pub(crate) fn get_random_bytes_full(buf: &mut [u8]) -> isize {
+    unsafe { esdm::esdm_rpcc_get_random_bytes_full(buf.as_mut_ptr(), buf.len()) }
+}
+
+pub(crate) fn get_random_bytes_pr(buf: &mut [u8]) -> isize {
+    unsafe { esdm::esdm_rpcc_get_random_bytes_pr(buf.as_mut_ptr(), buf.len()) }
+}
+
+pub(crate) fn get_random_bytes_min(buf: &mut [u8]) -> isize {
+    unsafe { esdm::esdm_rpcc_get_random_bytes_min(buf.as_mut_ptr(), buf.len()) }
+}
+
+pub(crate) fn get_random_bytes(buf: &mut [u8]) -> isize {
+    unsafe { esdm::esdm_rpcc_get_random_bytes(buf.as_mut_ptr(), buf.len()) }
+}
+
+pub(crate) fn get_seed(buf: &mut [u8], flags: u32) -> isize {
+    unsafe { esdm::esdm_rpcc_get_seed(buf.as_mut_ptr(), buf.len(), flags) }
+}
+
+pub(crate) fn write_data(data: &[u8]) -> i32 {
+    unsafe { esdm::esdm_rpcc_write_data(data.as_ptr(), data.len()) }
+}
+
+pub(crate) fn rnd_get_ent_cnt(ent_cnt: &mut u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_rnd_get_ent_cnt(ent_cnt) }
+}
+
+pub(crate) fn rnd_add_entropy(entropy_bytes: &[u8], entropy_count: u32) -> i32 {
+    unsafe {
+        esdm::esdm_rpcc_rnd_add_entropy(entropy_bytes.as_ptr(), entropy_bytes.len(), entropy_count)
+    }
+}
+
+pub(crate) fn rnd_add_to_ent_cnt(entropy_increment: u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_rnd_add_to_ent_cnt(entropy_increment) }
+}
+
+pub(crate) fn rnd_reseed_crng() -> i32 {
+    unsafe { esdm::esdm_rpcc_rnd_reseed_crng() }
+}
+
+pub(crate) fn rnd_clear_pool() -> i32 {
+    unsafe { esdm::esdm_rpcc_rnd_clear_pool() }
+}
+
+pub(crate) fn get_write_wakeup_thresh(write_wakeup_thresh: &mut u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_get_write_wakeup_thresh(write_wakeup_thresh) }
+}
+
+pub(crate) fn set_write_wakeup_thresh(write_wakeup_thresh: u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_set_write_wakeup_thresh(write_wakeup_thresh) }
+}
+
+pub(crate) fn get_min_reseed_secs(seconds: &mut u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_get_min_reseed_secs(seconds) }
+}
+
+pub(crate) fn set_min_reseed_secs(seconds: u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_set_min_reseed_secs(seconds) }
+}
+
+pub(crate) fn get_poolsize(poolsize: &mut u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_get_poolsize(poolsize) }
+}
+
+pub(crate) fn get_ent_lvl(ent_lvl: &mut u32) -> i32 {
+    unsafe { esdm::esdm_rpcc_get_ent_lvl(ent_lvl) }
+}
+
+pub(crate) fn status(buf: &mut [u8]) -> i32 {
+    unsafe { esdm::esdm_rpcc_status(buf.as_mut_ptr().cast::<c_char>(), buf.len()) }
+}
+
+pub(crate) fn jent_status(buf: &mut [u8]) -> i32 {
+    unsafe { esdm::esdm_rpcc_jent_status(buf.as_mut_ptr().cast::<c_char>(), buf.len()) }
+}
+
+pub(crate) fn aux_init_wait_for_need_entropy() -> i32 {
+    unsafe { esdm_aux::esdm_aux_init_wait_for_need_entropy() }
+}
+
+pub(crate) fn aux_fini_wait_for_need_entropy() {
+    unsafe { esdm_aux::esdm_aux_fini_wait_for_need_entropy() };
+}
+
+/// waits until ESDM needs entropy or the absolute `CLOCK_MONOTONIC` deadline passed,
+/// returns `ETIMEDOUT` in the latter case and `-errno` on errors
+pub(crate) fn aux_timedwait_for_need_entropy(deadline: &libc::timespec) -> i32 {
+    let mut ts_esdm = esdm_aux::timespec {
+        tv_sec: deadline.tv_sec,
+        tv_nsec: deadline.tv_nsec,
+    };
+    match unsafe { esdm_aux::esdm_aux_timedwait_for_need_entropy(&raw mut ts_esdm) } {
+        // the semaphore wait fails with errno like other ESDM client calls
+        code if code == -libc::ETIMEDOUT => libc::ETIMEDOUT,
+        code => code,
+    }
+}
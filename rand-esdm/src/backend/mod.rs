@@ -0,0 +1,31 @@
+//! Selects the implementation used to talk to ESDM
+//!
+//! Both backends expose the same functions, mirroring the ESDM client library
+//! API (return codes are 0 or a negative errno, byte counts are returned as
+//! `isize`), but with safe Rust signatures.
+
+/// largest number of random bytes requested in one RPC, well below the 64 KiB
+/// message limit of the ESDM RPC protocol
+pub(crate) const MAX_CHUNK_SIZE: usize = 32 * 1024;
+
+/// socket of the unprivileged ESDM RPC service
+#[cfg(feature = "native")]
+pub(crate) const UNPRIV_SOCKET: &str = "/var/run/esdm-rpc-unpriv";
+
+/// socket of the privileged ESDM RPC service
+pub(crate) const PRIV_SOCKET: &str = "/var/run/esdm-rpc-priv";
+
+#[cfg(not(any(feature = "ffi", feature = "native")))]
+compile_error!("rand-esdm needs either the `ffi` or the `native` feature");
+
+#[cfg(all(feature = "ffi", not(feature = "native")))]
+mod ffi;
+#[cfg(all(feature = "ffi", not(feature = "native")))]
+pub(crate) use ffi::*;
+
+#[cfg(feature = "native")]
+mod native;
+#[cfg(feature = "native")]
+pub(crate) mod proto;
+#[cfg(feature = "native")]
+pub(crate) use native::*;
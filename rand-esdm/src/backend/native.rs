@@ -0,0 +1,410 @@
+//! Backend speaking the ESDM RPC protocol natively over Unix sockets
+//!
+//! Every RPC uses a fresh connection, so there is no client state to set up,
+//! share between threads or carry over a `fork()`. The need-entropy
+//! notification of the aux client is emulated by polling the entropy count
+//! against the write wakeup threshold. Like the semaphore of the C client it
+//! fires once per drop below the threshold: a thread, which was told that ESDM
+//! needs entropy, is only told again after the count reached the threshold in
+//! between.
+
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use zeroize::Zeroizing;
+
+use super::proto::{self, Encoder, Message, RequestHeader, ResponseHeader, privileged, unpriv};
+use super::{PRIV_SOCKET, UNPRIV_SOCKET};
+
+/// how often the emulated aux notification re-checks the entropy count
+const AUX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how often a pending response is interrupted to ask the interrupt callback,
+/// like the C client does
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static REQUEST_ID: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    /// true, if the last need for entropy reported to this thread still lasts
+    static NEED_REPORTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// socket paths replacing the defaults (unprivileged, privileged)
+static SOCKET_PATHS: RwLock<Option<(PathBuf, PathBuf)>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Unpriv,
+    Priv,
+}
+
+/// overrides the socket paths of both RPC services, `None` restores the defaults
+pub(crate) fn set_socket_paths(paths: Option<(PathBuf, PathBuf)>) {
+    *SOCKET_PATHS
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = paths;
+}
+
+/// socket of the privileged service, as used by the next connection
+pub(crate) fn priv_socket_path() -> PathBuf {
+    socket_path(Service::Priv)
+}
+
+fn socket_path(service: Service) -> PathBuf {
+    let paths = SOCKET_PATHS
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    match (service, paths.as_ref()) {
+        (Service::Unpriv, Some((unpriv, _))) => unpriv.clone(),
+        (Service::Priv, Some((_, privileged))) => privileged.clone(),
+        (Service::Unpriv, None) => PathBuf::from(UNPRIV_SOCKET),
+        (Service::Priv, None) => PathBuf::from(PRIV_SOCKET),
+    }
+}
+
+/// converts an I/O error into a negative errno like the C client returns
+fn errno(err: &io::Error) -> i32 {
+    match err.raw_os_error() {
+        Some(code) => -code,
+        None if err.kind() == io::ErrorKind::UnexpectedEof => -libc::ECONNRESET,
+        None => -libc::EIO,
+    }
+}
+
+fn connect(service: Service) -> Result<UnixStream, i32> {
+    UnixStream::connect(socket_path(service)).map_err(|e| errno(&e))
+}
+
+/// fills `buf` from `stream`, fails with `-EINTR` once the call is cancelled
+fn read_full(stream: &mut UnixStream, mut buf: &mut [u8]) -> Result<(), i32> {
+    while !buf.is_empty() {
+        match stream.read(buf) {
+            Ok(0) => return Err(-libc::ECONNRESET),
+            Ok(n) => buf = &mut buf[n..],
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if crate::cancel::rpc_interrupt() {
+                    return Err(-libc::EINTR);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(errno(&e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// sends one request and returns the body of the matching response, which
+/// may carry random bytes and is zeroed on drop
+fn call(service: Service, method: u32, request: &[u8]) -> Result<Zeroizing<Vec<u8>>, i32> {
+    let mut stream = connect(service)?;
+    stream
+        .set_read_timeout(Some(INTERRUPT_POLL_INTERVAL))
+        .map_err(|e| errno(&e))?;
+
+    let header = RequestHeader {
+        message_length: u32::try_from(request.len()).map_err(|_| -libc::EMSGSIZE)?,
+        method_index: method,
+        request_id: REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+    };
+    stream
+        .write_all(&header.to_bytes())
+        .and_then(|()| stream.write_all(request))
+        .map_err(|e| errno(&e))?;
+
+    let mut response_header = [0u8; proto::SC_HEADER_LEN];
+    read_full(&mut stream, &mut response_header)?;
+    let response_header = ResponseHeader::from_bytes(&response_header);
+
+    if response_header.request_id != header.request_id || response_header.method_index != method {
+        return Err(-libc::EPROTO);
+    }
+    if response_header.status_code != proto::STATUS_SUCCESS {
+        return Err(-libc::EIO);
+    }
+
+    let len = usize::try_from(response_header.message_length).map_err(|_| -libc::EMSGSIZE)?;
+    if len > proto::MAX_MSG_SIZE {
+        return Err(-libc::EMSGSIZE);
+    }
+
+    let mut body = Zeroizing::new(vec![0u8; len]);
+    read_full(&mut stream, &mut body)?;
+
+    Ok(body)
+}
+
+/// executes an RPC, whose response carries the ESDM return code in field 1
+fn rpc<T>(
+    service: Service,
+    method: u32,
+    request: &[u8],
+    parse: impl FnOnce(&Message) -> Option<T>,
+) -> Result<T, i32> {
+    let body = call(service, method, request)?;
+    let msg = Message::decode(&body).ok_or(-libc::EPROTO)?;
+
+    let ret = msg.int32(1).ok_or(-libc::EPROTO)?;
+    if ret < 0 {
+        return Err(ret);
+    }
+
+    parse(&msg).ok_or(-libc::EPROTO)
+}
+
+fn ret_code(result: Result<(), i32>) -> i32 {
+    result.err().unwrap_or(0)
+}
+
+pub(crate) fn init_unpriv_service() -> i32 {
+    ret_code(connect(Service::Unpriv).map(drop))
+}
+
+pub(crate) fn fini_unpriv_service() {}
+
+pub(crate) fn init_priv_service() -> i32 {
+    ret_code(connect(Service::Priv).map(drop))
+}
+
+pub(crate) fn fini_priv_service() {}
+
+/// no-op: the C client sizes its per-node connection pool with this value,
+/// the native client does not keep connections around
+pub(crate) fn set_max_online_nodes(_nodes: u32) {}
+
+fn get_random_bytes_of(method: u32, buf: &mut [u8]) -> isize {
+    let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+    let request = Encoder::new().uint32(1, len).finish();
+
+    match rpc(Service::Unpriv, method, &request, |msg| {
+        msg.bytes(2).map(|random| Zeroizing::new(random.to_vec()))
+    }) {
+        Ok(random) => {
+            let copied = random.len().min(buf.len());
+            buf[..copied].copy_from_slice(&random[..copied]);
+            isize::try_from(copied).unwrap()
+        }
+        Err(code) => isize::try_from(code).unwrap(),
+    }
+}
+
+pub(crate) fn get_random_bytes_full(buf: &mut [u8]) -> isize {
+    get_random_bytes_of(unpriv::GET_RANDOM_BYTES_FULL, buf)
+}
+
+pub(crate) fn get_random_bytes_pr(buf: &mut [u8]) -> isize {
+    get_random_bytes_of(unpriv::GET_RANDOM_BYTES_PR, buf)
+}
+
+pub(crate) fn get_random_bytes_min(buf: &mut [u8]) -> isize {
+    get_random_bytes_of(unpriv::GET_RANDOM_BYTES_MIN, buf)
+}
+
+pub(crate) fn get_random_bytes(buf: &mut [u8]) -> isize {
+    get_random_bytes_of(unpriv::GET_RANDOM_BYTES, buf)
+}
+
+pub(crate) fn get_seed(buf: &mut [u8], flags: u32) -> isize {
+    let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+    let request = Encoder::new().uint32(1, len).uint32(2, flags).finish();
+
+    // unlike other RPCs, a failed request carries data: the needed buffer size
+    // on -EMSGSIZE
+    let result = call(Service::Unpriv, unpriv::GET_SEED, &request).and_then(|body| {
+        let msg = Message::decode(&body).ok_or(-libc::EPROTO)?;
+        let ret = msg.int32(1).ok_or(-libc::EPROTO)?;
+        let data = msg.bytes(2).unwrap_or_default();
+        let copied = data.len().min(buf.len());
+        buf[..copied].copy_from_slice(&data[..copied]);
+
+        if ret < 0 { Err(ret) } else { Ok(copied) }
+    });
+
+    match result {
+        Ok(copied) => isize::try_from(copied).unwrap(),
+        Err(code) => isize::try_from(code).unwrap(),
+    }
+}
+
+pub(crate) fn write_data(data: &[u8]) -> i32 {
+    let request = Encoder::new().bytes(1, data).finish();
+    ret_code(rpc(Service::Unpriv, unpriv::WRITE_DATA, &request, |_| {
+        Some(())
+    }))
+}
+
+fn get_u32(service: Service, method: u32, value: &mut u32) -> i32 {
+    ret_code(rpc(service, method, &[], |msg| msg.uint32(2)).map(|v| *value = v))
+}
+
+pub(crate) fn rnd_get_ent_cnt(ent_cnt: &mut u32) -> i32 {
+    get_u32(Service::Unpriv, unpriv::RND_GET_ENT_CNT, ent_cnt)
+}
+
+pub(crate) fn rnd_add_entropy(entropy_bytes: &[u8], entropy_count: u32) -> i32 {
+    let request = Encoder::new()
+        .bytes(1, entropy_bytes)
+        .uint32(2, entropy_count)
+        .finish();
+    ret_code(rpc(
+        Service::Priv,
+        privileged::RND_ADD_ENTROPY,
+        &request,
+        |_| Some(()),
+    ))
+}
+
+pub(crate) fn rnd_add_to_ent_cnt(entropy_increment: u32) -> i32 {
+    let request = Encoder::new().uint32(1, entropy_increment).finish();
+    ret_code(rpc(
+        Service::Priv,
+        privileged::RND_ADD_TO_ENT_CNT,
+        &request,
+        |_| Some(()),
+    ))
+}
+
+pub(crate) fn rnd_reseed_crng() -> i32 {
+    ret_code(rpc(Service::Priv, privileged::RND_RESEED_CRNG, &[], |_| {
+        Some(())
+    }))
+}
+
+pub(crate) fn rnd_clear_pool() -> i32 {
+    ret_code(rpc(Service::Priv, privileged::RND_CLEAR_POOL, &[], |_| {
+        Some(())
+    }))
+}
+
+pub(crate) fn get_write_wakeup_thresh(write_wakeup_thresh: &mut u32) -> i32 {
+    get_u32(
+        Service::Unpriv,
+        unpriv::GET_WRITE_WAKEUP_THRESH,
+        write_wakeup_thresh,
+    )
+}
+
+pub(crate) fn set_write_wakeup_thresh(write_wakeup_thresh: u32) -> i32 {
+    let request = Encoder::new().uint32(1, write_wakeup_thresh).finish();
+    ret_code(rpc(
+        Service::Priv,
+        privileged::SET_WRITE_WAKEUP_THRESH,
+        &request,
+        |_| Some(()),
+    ))
+}
+
+pub(crate) fn get_min_reseed_secs(seconds: &mut u32) -> i32 {
+    get_u32(Service::Unpriv, unpriv::GET_MIN_RESEED_SECS, seconds)
+}
+
+pub(crate) fn set_min_reseed_secs(seconds: u32) -> i32 {
+    let request = Encoder::new().uint32(1, seconds).finish();
+    ret_code(rpc(
+        Service::Priv,
+        privileged::SET_MIN_RESEED_SECS,
+        &request,
+        |_| Some(()),
+    ))
+}
+
+pub(crate) fn get_poolsize(poolsize: &mut u32) -> i32 {
+    get_u32(Service::Unpriv, unpriv::RND_GET_POOLSIZE, poolsize)
+}
+
+pub(crate) fn get_ent_lvl(ent_lvl: &mut u32) -> i32 {
+    get_u32(Service::Unpriv, unpriv::GET_ENT_LVL, ent_lvl)
+}
+
+/// copies a status text into `buf` as NUL-terminated string, truncating if needed
+fn get_status_text(method: u32, buf: &mut [u8]) -> i32 {
+    let text = match rpc(Service::Unpriv, method, &[], |msg| {
+        msg.bytes(2).map(<[u8]>::to_vec)
+    }) {
+        Ok(text) => text,
+        Err(code) => return code,
+    };
+
+    let Some(max_len) = buf.len().checked_sub(1) else {
+        return -libc::EINVAL;
+    };
+    let len = text.len().min(max_len);
+    buf[..len].copy_from_slice(&text[..len]);
+    buf[len] = 0;
+
+    0
+}
+
+pub(crate) fn status(buf: &mut [u8]) -> i32 {
+    get_status_text(unpriv::STATUS, buf)
+}
+
+pub(crate) fn jent_status(buf: &mut [u8]) -> i32 {
+    get_status_text(unpriv::JENT_STATUS, buf)
+}
+
+pub(crate) fn aux_init_wait_for_need_entropy() -> i32 {
+    init_unpriv_service()
+}
+
+pub(crate) fn aux_fini_wait_for_need_entropy() {}
+
+fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut ts) };
+    timespec_to_duration(&ts)
+}
+
+fn timespec_to_duration(ts: &libc::timespec) -> Duration {
+    Duration::new(
+        u64::try_from(ts.tv_sec).unwrap_or(0),
+        u32::try_from(ts.tv_nsec).unwrap_or(0),
+    )
+}
+
+/// true, if the entropy count is below the write wakeup threshold, `None` if
+/// either cannot be read
+fn needs_entropy() -> Option<bool> {
+    let mut ent_cnt = 0;
+    let mut write_wakeup_thresh = 0;
+
+    (rnd_get_ent_cnt(&mut ent_cnt) == 0 && get_write_wakeup_thresh(&mut write_wakeup_thresh) == 0)
+        .then_some(ent_cnt < write_wakeup_thresh)
+}
+
+/// waits until ESDM needs entropy or the absolute `CLOCK_MONOTONIC` deadline passed,
+/// returns `ETIMEDOUT` in the latter case
+pub(crate) fn aux_timedwait_for_need_entropy(deadline: &libc::timespec) -> i32 {
+    let deadline = timespec_to_duration(deadline);
+
+    loop {
+        match needs_entropy() {
+            Some(true) if !NEED_REPORTED.get() => {
+                NEED_REPORTED.set(true);
+                return 0;
+            }
+            Some(false) => NEED_REPORTED.set(false),
+            _ => {}
+        }
+
+        let now = monotonic_now();
+        if now >= deadline {
+            return libc::ETIMEDOUT;
+        }
+        std::thread::sleep(AUX_POLL_INTERVAL.min(deadline.saturating_sub(now)));
+    }
+}
@@ -0,0 +1,298 @@
+//! Wire format of the ESDM RPC protocol
+//!
+//! ESDM exchanges protobuf-c encoded messages over Unix stream sockets. Every
+//! request is prefixed with a client header, every answer with a server header,
+//! all header fields are little-endian `u32`. The method indices follow the
+//! order of the `rpc` entries in ESDM's `unpriv_access.proto` and
+//! `priv_access.proto` service definitions.
+
+use zeroize::Zeroizing;
+
+/// maximum size of a message body accepted from the peer
+pub(crate) const MAX_MSG_SIZE: usize = 65536;
+
+/// length of the header sent by the client in front of every request
+pub(crate) const CS_HEADER_LEN: usize = 12;
+
+/// length of the header sent by the server in front of every response
+pub(crate) const SC_HEADER_LEN: usize = 16;
+
+/// server status code signalling a successfully executed RPC
+pub(crate) const STATUS_SUCCESS: u32 = 0;
+
+/// server status code signalling a failed RPC
+#[cfg(feature = "mock")]
+pub(crate) const STATUS_SERVICE_FAILED: u32 = 1;
+
+/// method indices of the unprivileged service
+pub(crate) mod unpriv {
+    pub(crate) const STATUS: u32 = 0;
+    pub(crate) const GET_RANDOM_BYTES_FULL: u32 = 1;
+    pub(crate) const GET_RANDOM_BYTES_MIN: u32 = 2;
+    pub(crate) const GET_RANDOM_BYTES_PR: u32 = 3;
+    pub(crate) const GET_SEED: u32 = 4;
+    pub(crate) const WRITE_DATA: u32 = 5;
+    pub(crate) const RND_GET_ENT_CNT: u32 = 6;
+    pub(crate) const RND_GET_POOLSIZE: u32 = 7;
+    pub(crate) const GET_WRITE_WAKEUP_THRESH: u32 = 8;
+    pub(crate) const GET_MIN_RESEED_SECS: u32 = 9;
+    pub(crate) const GET_RANDOM_BYTES: u32 = 10;
+    pub(crate) const GET_ENT_LVL: u32 = 11;
+    pub(crate) const JENT_STATUS: u32 = 12;
+}
+
+/// method indices of the privileged service
+pub(crate) mod privileged {
+    pub(crate) const RND_ADD_TO_ENT_CNT: u32 = 0;
+    pub(crate) const RND_ADD_ENTROPY: u32 = 1;
+    pub(crate) const RND_CLEAR_POOL: u32 = 2;
+    pub(crate) const RND_RESEED_CRNG: u32 = 3;
+    pub(crate) const SET_WRITE_WAKEUP_THRESH: u32 = 4;
+    pub(crate) const SET_MIN_RESEED_SECS: u32 = 5;
+}
+
+/// header sent in front of every request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RequestHeader {
+    pub(crate) message_length: u32,
+    pub(crate) method_index: u32,
+    pub(crate) request_id: u32,
+}
+
+impl RequestHeader {
+    pub(crate) fn to_bytes(self) -> [u8; CS_HEADER_LEN] {
+        let mut out = [0u8; CS_HEADER_LEN];
+        out[0..4].copy_from_slice(&self.message_length.to_le_bytes());
+        out[4..8].copy_from_slice(&self.method_index.to_le_bytes());
+        out[8..12].copy_from_slice(&self.request_id.to_le_bytes());
+        out
+    }
+
+    #[cfg(feature = "mock")]
+    pub(crate) fn from_bytes(bytes: &[u8; CS_HEADER_LEN]) -> Self {
+        RequestHeader {
+            message_length: le_u32(&bytes[0..4]),
+            method_index: le_u32(&bytes[4..8]),
+            request_id: le_u32(&bytes[8..12]),
+        }
+    }
+}
+
+/// header sent in front of every response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResponseHeader {
+    pub(crate) status_code: u32,
+    pub(crate) method_index: u32,
+    pub(crate) message_length: u32,
+    pub(crate) request_id: u32,
+}
+
+impl ResponseHeader {
+    #[cfg(feature = "mock")]
+    pub(crate) fn to_bytes(self) -> [u8; SC_HEADER_LEN] {
+        let mut out = [0u8; SC_HEADER_LEN];
+        out[0..4].copy_from_slice(&self.status_code.to_le_bytes());
+        out[4..8].copy_from_slice(&self.method_index.to_le_bytes());
+        out[8..12].copy_from_slice(&self.message_length.to_le_bytes());
+        out[12..16].copy_from_slice(&self.request_id.to_le_bytes());
+        out
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8; SC_HEADER_LEN]) -> Self {
+        ResponseHeader {
+            status_code: le_u32(&bytes[0..4]),
+            method_index: le_u32(&bytes[4..8]),
+            message_length: le_u32(&bytes[8..12]),
+            request_id: le_u32(&bytes[12..16]),
+        }
+    }
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// bytes reserved beyond the data of a bytes field: its key and length and a
+/// few scalar fields behind it
+const TRAILING_FIELDS_RESERVE: usize = 48;
+
+/// encoder for the few protobuf field types used by ESDM messages
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Encoder::default()
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(u8::try_from(value & 0x7f).unwrap() | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(u8::try_from(value).unwrap());
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        self.varint((u64::from(field) << 3) | wire_type);
+    }
+
+    pub(crate) fn uint32(mut self, field: u32, value: u32) -> Self {
+        self.key(field, WIRE_VARINT);
+        self.varint(u64::from(value));
+        self
+    }
+
+    #[cfg(feature = "mock")]
+    pub(crate) fn int32(mut self, field: u32, value: i32) -> Self {
+        // negative int32 values are sign extended to 64 bit on the wire
+        self.key(field, WIRE_VARINT);
+        self.varint(i64::from(value).cast_unsigned());
+        self
+    }
+
+    pub(crate) fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        // no reallocation for the fields behind, it would leave a copy of the
+        // (possibly secret) data in freed memory
+        self.buf.reserve(value.len() + TRAILING_FIELDS_RESERVE);
+        self.key(field, WIRE_LEN);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    /// encoded message, zeroed on drop
+    pub(crate) fn finish(self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(self.buf)
+    }
+}
+
+/// single decoded protobuf field value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// decoded protobuf message, unknown fields are kept but never interpreted
+#[derive(Debug, Default)]
+pub(crate) struct Message<'a> {
+    fields: Vec<(u32, Value<'a>)>,
+}
+
+impl<'a> Message<'a> {
+    /// decodes a message, returns `None` on malformed input
+    pub(crate) fn decode(mut buf: &'a [u8]) -> Option<Self> {
+        let mut msg = Message::default();
+
+        while !buf.is_empty() {
+            let key = read_varint(&mut buf)?;
+            let field = u32::try_from(key >> 3).ok()?;
+            let value = match key & 0x7 {
+                WIRE_VARINT => Value::Varint(read_varint(&mut buf)?),
+                WIRE_LEN => {
+                    let len = usize::try_from(read_varint(&mut buf)?).ok()?;
+                    let (value, rest) = buf.split_at_checked(len)?;
+                    buf = rest;
+                    Value::Bytes(value)
+                }
+                WIRE_FIXED64 => {
+                    buf = buf.get(8..)?;
+                    Value::Fixed
+                }
+                WIRE_FIXED32 => {
+                    buf = buf.get(4..)?;
+                    Value::Fixed
+                }
+                _ => return None,
+            };
+            msg.fields.push((field, value));
+        }
+
+        Some(msg)
+    }
+
+    fn varint(&self, field: u32) -> Option<u64> {
+        self.fields.iter().rev().find_map(|(f, v)| match v {
+            Value::Varint(value) if *f == field => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn uint32(&self, field: u32) -> Option<u32> {
+        // protobuf truncates oversized varints to the declared type
+        self.varint(field)
+            .map(|v| u32::try_from(v & 0xFFFF_FFFF).unwrap())
+    }
+
+    pub(crate) fn int32(&self, field: u32) -> Option<i32> {
+        self.uint32(field).map(u32::cast_signed)
+    }
+
+    pub(crate) fn bytes(&self, field: u32) -> Option<&'a [u8]> {
+        self.fields.iter().rev().find_map(|(f, v)| match v {
+            Value::Bytes(value) if *f == field => Some(*value),
+            _ => None,
+        })
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let encoded = Encoder::new()
+            .uint32(1, (-libc::EAGAIN).cast_unsigned())
+            .bytes(2, b"random")
+            .uint32(3, 300)
+            .finish();
+
+        let msg = Message::decode(&encoded).unwrap();
+        assert_eq!(msg.int32(1), Some(-libc::EAGAIN));
+        assert_eq!(msg.bytes(2), Some(&b"random"[..]));
+        assert_eq!(msg.uint32(3), Some(300));
+        assert_eq!(msg.uint32(4), None);
+
+        assert!(Message::decode(&encoded[..encoded.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let header = RequestHeader {
+            message_length: 2,
+            method_index: unpriv::GET_RANDOM_BYTES_PR,
+            request_id: 0x0102_0304,
+        };
+        assert_eq!(header.to_bytes(), [2, 0, 0, 0, 3, 0, 0, 0, 4, 3, 2, 1]);
+
+        let header = ResponseHeader::from_bytes(&[0, 0, 0, 0, 1, 0, 0, 0, 42, 0, 0, 0, 7, 0, 0, 0]);
+        assert_eq!(header.status_code, STATUS_SUCCESS);
+        assert_eq!(header.method_index, unpriv::GET_RANDOM_BYTES_FULL);
+        assert_eq!(header.message_length, 42);
+        assert_eq!(header.request_id, 7);
+    }
+}
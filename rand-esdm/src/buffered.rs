@@ -0,0 +1,254 @@
+//! Buffered random numbers for many small requests
+//!
+//! [`BufferedEsdmRng`] fetches a 256 bit key from ESDM (fully seeded mode) and
+//! expands it with `ChaCha20` into a buffer, serving small requests without an
+//! RPC round trip. It uses fast-key-erasure: the first bytes of every
+//! generated block replace the key, and every byte is wiped from the buffer as
+//! soon as it was handed out, so a later state compromise does not reveal
+//! earlier output. A fresh key is fetched from ESDM after `refresh_limit`
+//! bytes and after a fork.
+
+use std::num::NonZeroUsize;
+
+use chacha20::ChaCha20Rng;
+use rand_core::{Rng, SeedableRng, TryCryptoRng, TryRng};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{EsdmConnection, EsdmError, EsdmRngType, fork};
+
+/// length of the `ChaCha20` key in bytes
+const KEY_LEN: usize = 32;
+
+/// default number of random bytes generated in one go
+const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// default number of bytes generated from one ESDM key
+const DEFAULT_REFRESH_LIMIT: u64 = 1 << 20;
+
+/// keystream state derived from one ESDM key
+struct KeyedState {
+    chacha: ChaCha20Rng,
+    // generated bytes, `buf[pos..]` are not handed out yet, the rest is wiped
+    buf: Zeroizing<Vec<u8>>,
+    pos: usize,
+    generated: u64,
+    // fork generation the key was fetched in
+    generation: u64,
+}
+
+impl KeyedState {
+    /// expands the next block into the buffer and erases the key it was generated with
+    fn generate(&mut self, buffer_size: usize) {
+        self.buf.resize(KEY_LEN + buffer_size, 0);
+        self.chacha.fill_bytes(&mut self.buf);
+
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&self.buf[..KEY_LEN]);
+        self.chacha = ChaCha20Rng::from_seed(*key);
+        self.buf[..KEY_LEN].zeroize();
+
+        self.pos = KEY_LEN;
+        self.generated += buffer_size as u64;
+    }
+}
+
+/// [`EsdmRng`](crate::EsdmRng) variant serving small requests from a buffer
+pub struct BufferedEsdmRng {
+    conn: EsdmConnection,
+    buffer_size: usize,
+    refresh_limit: u64,
+    state: Option<KeyedState>,
+}
+
+impl std::fmt::Debug for BufferedEsdmRng {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print buffered random bytes
+        f.debug_struct("BufferedEsdmRng")
+            .field("buffer_size", &self.buffer_size)
+            .field("refresh_limit", &self.refresh_limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BufferedEsdmRng {
+    pub fn new() -> Result<Self, EsdmError> {
+        Ok(Self::with_connection(EsdmConnection::new()?))
+    }
+
+    pub(crate) fn with_connection(conn: EsdmConnection) -> Self {
+        BufferedEsdmRng {
+            conn,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            refresh_limit: DEFAULT_REFRESH_LIMIT,
+            state: None,
+        }
+    }
+
+    /// sets the number of bytes generated in one go, requests of at least this
+    /// size are fetched from ESDM directly
+    #[must_use]
+    pub fn with_buffer_size(mut self, buffer_size: NonZeroUsize) -> Self {
+        self.buffer_size = buffer_size.get();
+        self.discard();
+        self
+    }
+
+    /// sets the number of bytes generated, before a fresh key is fetched from ESDM
+    #[must_use]
+    pub fn with_refresh_limit(mut self, refresh_limit: u64) -> Self {
+        self.refresh_limit = refresh_limit;
+        self
+    }
+
+    #[must_use]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    #[must_use]
+    pub fn refresh_limit(&self) -> u64 {
+        self.refresh_limit
+    }
+
+    /// the connection used by this generator
+    #[must_use]
+    pub fn connection(&self) -> &EsdmConnection {
+        &self.conn
+    }
+
+    /// wipes all buffered random bytes, the next request fetches a fresh key
+    pub fn discard(&mut self) {
+        // KeyedState wipes buffer and ChaCha20 state on drop
+        self.state = None;
+    }
+
+    /// returns the state to serve the next bytes from, refilled if necessary
+    fn state(&mut self) -> Result<&mut KeyedState, EsdmError> {
+        let generation = fork::generation();
+        let needs_key = self.state.as_ref().is_none_or(|state| {
+            state.generation != generation
+                || (state.pos == state.buf.len() && state.generated >= self.refresh_limit)
+        });
+
+        if needs_key {
+            self.state = None;
+
+            let mut key = Zeroizing::new([0u8; KEY_LEN]);
+            self.conn
+                .fill_bytes(EsdmRngType::FullySeeded, key.as_mut_slice())?;
+
+            let state = self.state.insert(KeyedState {
+                chacha: ChaCha20Rng::from_seed(*key),
+                buf: Zeroizing::new(Vec::new()),
+                pos: 0,
+                generated: 0,
+                generation,
+            });
+            state.generate(self.buffer_size);
+
+            return Ok(state);
+        }
+
+        let Some(state) = self.state.as_mut() else {
+            unreachable!("state checked above");
+        };
+        if state.pos == state.buf.len() {
+            state.generate(self.buffer_size);
+        }
+
+        Ok(state)
+    }
+}
+
+impl TryRng for BufferedEsdmRng {
+    type Error = EsdmError;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        let mut bytes = Zeroizing::new([0u8; 4]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
+
+        Ok(u32::from_ne_bytes(*bytes))
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        let mut bytes = Zeroizing::new([0u8; 8]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
+
+        Ok(u64::from_ne_bytes(*bytes))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        if dst.len() >= self.buffer_size {
+            return self.conn.fill_bytes(EsdmRngType::FullySeeded, dst);
+        }
+
+        let mut filled = 0;
+        while filled < dst.len() {
+            let state = self.state()?;
+            let len = (dst.len() - filled).min(state.buf.len() - state.pos);
+            let served = &mut state.buf[state.pos..state.pos + len];
+            dst[filled..filled + len].copy_from_slice(served);
+            served.zeroize();
+            state.pos += len;
+            filled += len;
+        }
+
+        Ok(())
+    }
+}
+
+/// keys come from the fully seeded ESDM DRNG, `ChaCha20` expands them
+impl TryCryptoRng for BufferedEsdmRng {}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::EsdmOperation;
+    use crate::mock::MockEsdmServer;
+
+    #[test]
+    fn test_buffered_small_requests() {
+        let server = MockEsdmServer::start().unwrap();
+        let mut rng = BufferedEsdmRng::new()
+            .unwrap()
+            .with_buffer_size(NonZeroUsize::new(256).unwrap());
+
+        let values: Vec<u64> = (0..1000).map(|_| rng.try_next_u64().unwrap()).collect();
+        assert!(values.windows(2).all(|w| w[0] != w[1]));
+
+        // one key for all of them
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 1);
+
+        // served bytes are wiped from the buffer
+        let state = rng.state.as_ref().unwrap();
+        assert!(state.buf[..state.pos].iter().all(|b| *b == 0));
+
+        // large requests are fetched directly
+        let mut buf = [0u8; 256];
+        rng.try_fill_bytes(&mut buf).unwrap();
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 2);
+    }
+
+    #[test]
+    fn test_buffered_refresh() {
+        let server = MockEsdmServer::start().unwrap();
+        let mut rng = BufferedEsdmRng::new()
+            .unwrap()
+            .with_buffer_size(NonZeroUsize::new(64).unwrap())
+            .with_refresh_limit(128);
+
+        // 64 * 8 bytes = 8 buffers, a fresh key every 2 buffers
+        for _ in 0..64 {
+            rng.try_next_u64().unwrap();
+        }
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 4);
+
+        rng.discard();
+        rng.try_next_u32().unwrap();
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 5);
+
+        server.set_seed_level(crate::EsdmSeedLevel::Unseeded);
+        rng.discard();
+        assert!(rng.try_next_u32().is_err());
+    }
+}
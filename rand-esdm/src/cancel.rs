@@ -0,0 +1,184 @@
+//! Cancellation of blocking ESDM calls
+//!
+//! A [`CancellationToken`] is shared between the code waiting for ESDM and the
+//! code shutting it down, e.g. a signal handler thread. Calls observe the token
+//! of their handle (see
+//! [`EsdmConnection::with_cancellation`](crate::EsdmConnection::with_cancellation))
+//! or else the one of [`CancellationToken::run`] on the calling thread. While a
+//! call runs, its token is kept in a thread-local, where the interrupt callback
+//! registered with the ESDM client library checks it: blocking RPCs, retry
+//! delays and waits return [`EsdmError::Cancelled`](crate::EsdmError::Cancelled)
+//! shortly after cancelling.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+thread_local! {
+    static THREAD_TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: Mutex<bool>,
+    wakeup: Condvar,
+}
+
+/// Cancels waits of every handle it was passed to
+///
+/// Clones share the cancellation state. A cancelled token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancelState>,
+}
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// cancels all current and future waits observing this token
+    pub fn cancel(&self) {
+        *self
+            .state
+            .cancelled
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = true;
+        self.state.wakeup.notify_all();
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        *self
+            .state
+            .cancelled
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// runs `f` with this token observed by the ESDM calls it makes on the
+    /// current thread, unless their handle has a token of its own
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _scope = enter(Some(self));
+        f()
+    }
+
+    /// sleeps for `dur` or until cancelled, true if cancelled
+    pub(crate) fn sleep(&self, dur: Duration) -> bool {
+        let cancelled = self
+            .state
+            .cancelled
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (cancelled, _) = self
+            .state
+            .wakeup
+            .wait_timeout_while(cancelled, dur, |cancelled| !*cancelled)
+            .unwrap_or_else(PoisonError::into_inner);
+        *cancelled
+    }
+}
+
+/// restores the token of the current thread on drop
+pub(crate) struct Scope {
+    entered: bool,
+    previous: Option<CancellationToken>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.entered {
+            THREAD_TOKEN.set(self.previous.take());
+        }
+    }
+}
+
+/// makes `token` the one of the current thread until the returned scope is
+/// dropped, `None` keeps the current one
+pub(crate) fn enter(token: Option<&CancellationToken>) -> Scope {
+    match token {
+        Some(token) => Scope {
+            entered: true,
+            previous: THREAD_TOKEN.replace(Some(token.clone())),
+        },
+        None => Scope {
+            entered: false,
+            previous: None,
+        },
+    }
+}
+
+/// true, if the token of the current thread is cancelled
+pub(crate) fn is_cancelled() -> bool {
+    THREAD_TOKEN
+        .try_with(|token| {
+            token
+                .borrow()
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+        })
+        .unwrap_or(false)
+}
+
+/// sleeps for `dur` or until the token of the current thread is cancelled,
+/// true if cancelled
+pub(crate) fn sleep(dur: Duration) -> bool {
+    if let Some(token) = THREAD_TOKEN.with_borrow(Clone::clone) {
+        token.sleep(dur)
+    } else {
+        std::thread::sleep(dur);
+        false
+    }
+}
+
+/// interrupt callback of the ESDM client library, asked while an RPC blocks
+pub(crate) extern "C" fn rpc_interrupt() -> bool {
+    is_cancelled()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_cancel_wakes_sleep() {
+        let token = CancellationToken::new();
+        assert!(!token.sleep(Duration::from_millis(1)));
+
+        let canceller = token.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+
+        let start = Instant::now();
+        assert!(token.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(token.is_cancelled());
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_thread_token() {
+        let outer = CancellationToken::new();
+        let inner = CancellationToken::new();
+        inner.cancel();
+
+        assert!(!is_cancelled());
+        outer.run(|| {
+            assert!(!rpc_interrupt());
+            {
+                let _scope = enter(Some(&inner));
+                assert!(rpc_interrupt());
+                assert!(sleep(Duration::from_secs(10)));
+            }
+            let _scope = enter(None);
+            assert!(!is_cancelled());
+            outer.cancel();
+            assert!(is_cancelled());
+        });
+        assert!(!is_cancelled());
+    }
+}
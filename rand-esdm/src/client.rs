@@ -0,0 +1,688 @@
+//! Client settings in one place
+//!
+//! [`EsdmClientBuilder`] documents the settings and where they are taken from.
+
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "native")]
+use crate::EsdmClientConfig;
+use crate::{
+    BufferedEsdmRng, EsdmAdmin, EsdmConnection, EsdmError, EsdmNotification, EsdmPrivConnection,
+    EsdmRng, EsdmRngType, RetryPolicy, SOCKET_DIR_ENV, backend, esdm_retry_policy,
+};
+
+const CONFIG_FILE_ENV: &str = "ESDM_CLIENT_CONFIG";
+const MAX_ONLINE_NODES_ENV: &str = "ESDM_MAX_ONLINE_NODES";
+const DEFAULT_MODE_ENV: &str = "ESDM_DEFAULT_MODE";
+const MAX_CHUNK_SIZE_ENV: &str = "ESDM_MAX_CHUNK_SIZE";
+const RETRY_MAX_ATTEMPTS_ENV: &str = "ESDM_RETRY_MAX_ATTEMPTS";
+const RETRY_DEADLINE_ENV: &str = "ESDM_RETRY_DEADLINE_MS";
+
+#[cfg(not(feature = "native"))]
+const SOCKETS_NEED_NATIVE: &str = "custom socket paths need the `native` backend";
+
+// 0 while the client library default is used
+static MAX_ONLINE_NODES: AtomicU32 = AtomicU32::new(0);
+
+/// limits the number of NUMA nodes the client library connects to
+#[deprecated(note = "use `EsdmClient::builder().max_online_nodes(..)`, which validates it")]
+pub fn esdm_set_max_online_nodes(nodes: u32) {
+    set_max_online_nodes(nodes);
+}
+
+/// the number of NUMA nodes the client library connects to, `None` while its
+/// default (all online nodes) is used
+#[must_use]
+pub fn esdm_max_online_nodes() -> Option<u32> {
+    match MAX_ONLINE_NODES.load(Ordering::Relaxed) {
+        0 => None,
+        nodes => Some(nodes),
+    }
+}
+
+fn set_max_online_nodes(nodes: u32) {
+    backend::set_max_online_nodes(nodes);
+    MAX_ONLINE_NODES.store(nodes, Ordering::Relaxed);
+}
+
+fn invalid(setting: impl Into<String>, reason: impl Into<String>) -> EsdmError {
+    EsdmError::InvalidConfig {
+        setting: setting.into(),
+        reason: reason.into(),
+    }
+}
+
+fn parse_mode(mode: &str) -> Option<EsdmRngType> {
+    match mode {
+        "fully-seeded" | "full" => Some(EsdmRngType::FullySeeded),
+        "prediction-resistant" | "pr" => Some(EsdmRngType::PredictionResistant),
+        "minimally-seeded" | "min" => Some(EsdmRngType::MinimallySeeded),
+        "non-blocking" => Some(EsdmRngType::NonBlocking),
+        _ => None,
+    }
+}
+
+/// parses the environment variable `name`, `None` if it is unset or empty
+fn parse_var<T: FromStr>(
+    vars: &impl Fn(&str) -> Option<OsString>,
+    name: &str,
+) -> Result<Option<T>, EsdmError>
+where
+    T::Err: ToString,
+{
+    let Some(value) = vars(name).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+    let value = value
+        .into_string()
+        .map_err(|_| invalid(name, "not valid UTF-8"))?;
+    value
+        .parse()
+        .map(Some)
+        .map_err(|err: T::Err| invalid(name, err.to_string()))
+}
+
+/// Settings of an [`EsdmClient`]
+///
+/// Created with [`EsdmClient::builder`], collects everything the ESDM client
+/// can be tuned with: socket locations, the number of NUMA nodes the client
+/// library connects to, the default random number type and the retry policy.
+/// [`build`] validates the settings, applies the process-wide ones and returns
+/// an [`EsdmClient`], which hands out connections and generators using them.
+/// Settings left unset keep the process-wide state as it is: the applied
+/// socket paths, the client library's number of NUMA nodes and the global
+/// retry policy.
+///
+/// Settings can be given in code, loaded from a TOML file (`toml` feature) and
+/// from environment variables; later sources override earlier ones:
+///
+/// | environment variable      | builder method                            |
+/// |---------------------------|-------------------------------------------|
+/// | `ESDM_CLIENT_CONFIG`      | [`load_file`] (path of a TOML file)       |
+/// | `ESDM_SOCKET_DIR`         | `socket_dir` (`native` backend)           |
+/// | `ESDM_MAX_ONLINE_NODES`   | [`max_online_nodes`]                      |
+/// | `ESDM_DEFAULT_MODE`       | [`default_mode`]                          |
+/// | `ESDM_MAX_CHUNK_SIZE`     | [`max_chunk_size`]                        |
+/// | `ESDM_RETRY_MAX_ATTEMPTS` | [`retry`], with the maximum attempts      |
+/// | `ESDM_RETRY_DEADLINE_MS`  | [`retry`], with the deadline              |
+///
+/// The TOML file uses the same settings as keys (`socket_dir`,
+/// `unpriv_socket`, `priv_socket`, `max_online_nodes`, `default_mode`,
+/// `max_chunk_size`) and a `[retry]` table (`max_attempts`,
+/// `initial_backoff_ms`, `max_backoff_ms`, `jitter`, `deadline_ms`). Modes are
+/// named `fully-seeded`, `prediction-resistant`, `minimally-seeded` and
+/// `non-blocking`.
+///
+/// Socket locations need the `native` backend, see
+/// [`SOCKET_DIR_ENV`](crate::SOCKET_DIR_ENV). With the `ffi` backend, the
+/// builder has no methods for them and rejects them in the environment or the
+/// file with [`EsdmError::InvalidConfig`].
+///
+/// [`build`]: Self::build
+/// [`load_file`]: Self::load_file
+/// [`max_online_nodes`]: Self::max_online_nodes
+/// [`default_mode`]: Self::default_mode
+/// [`max_chunk_size`]: Self::max_chunk_size
+/// [`retry`]: Self::retry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsdmClientBuilder {
+    #[cfg(feature = "native")]
+    sockets: Option<EsdmClientConfig>,
+    max_online_nodes: Option<u32>,
+    default_mode: EsdmRngType,
+    retry: Option<RetryPolicy>,
+    max_chunk_size: Option<usize>,
+}
+
+impl Default for EsdmClientBuilder {
+    fn default() -> Self {
+        EsdmClientBuilder {
+            #[cfg(feature = "native")]
+            sockets: None,
+            max_online_nodes: None,
+            default_mode: EsdmRngType::FullySeeded,
+            retry: None,
+            max_chunk_size: None,
+        }
+    }
+}
+
+impl EsdmClientBuilder {
+    /// socket paths of the ESDM services
+    #[cfg(feature = "native")]
+    #[must_use]
+    pub fn sockets(mut self, sockets: EsdmClientConfig) -> Self {
+        self.sockets = Some(sockets);
+        self
+    }
+
+    /// uses the sockets with their default names in `dir`
+    #[cfg(feature = "native")]
+    #[must_use]
+    pub fn socket_dir(self, dir: impl AsRef<Path>) -> Self {
+        self.sockets(EsdmClientConfig::new().with_socket_dir(dir))
+    }
+
+    /// connects to at most `nodes` NUMA nodes (at least 1), only the `ffi`
+    /// backend keeps connections per node
+    #[must_use]
+    pub fn max_online_nodes(mut self, nodes: u32) -> Self {
+        self.max_online_nodes = Some(nodes);
+        self
+    }
+
+    /// type of random numbers of [`EsdmClient::rng`], fully seeded by default
+    #[must_use]
+    pub fn default_mode(mut self, mode: EsdmRngType) -> Self {
+        self.default_mode = mode;
+        self
+    }
+
+    /// retry policy of all handles of the client
+    #[must_use]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// maximum number of random bytes requested per RPC (at least 1)
+    #[must_use]
+    pub fn max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = Some(max_chunk_size);
+        self
+    }
+
+    /// overrides the settings given by environment variables, after loading
+    /// the file named by `ESDM_CLIENT_CONFIG`
+    pub fn load_env(self) -> Result<Self, EsdmError> {
+        self.load_vars(&|name| std::env::var_os(name))
+    }
+
+    fn load_vars(mut self, vars: &impl Fn(&str) -> Option<OsString>) -> Result<Self, EsdmError> {
+        if let Some(path) = vars(CONFIG_FILE_ENV).filter(|path| !path.is_empty()) {
+            self = self.load_file(path)?;
+        }
+
+        if let Some(dir) = vars(SOCKET_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            #[cfg(feature = "native")]
+            {
+                self = self.socket_dir(dir);
+            }
+            #[cfg(not(feature = "native"))]
+            {
+                let _ = dir;
+                return Err(invalid(SOCKET_DIR_ENV, SOCKETS_NEED_NATIVE));
+            }
+        }
+        if let Some(nodes) = parse_var(vars, MAX_ONLINE_NODES_ENV)? {
+            self.max_online_nodes = Some(nodes);
+        }
+        if let Some(mode) = parse_var::<String>(vars, DEFAULT_MODE_ENV)? {
+            self.default_mode =
+                parse_mode(&mode).ok_or_else(|| invalid(DEFAULT_MODE_ENV, "unknown mode"))?;
+        }
+        if let Some(max_chunk_size) = parse_var(vars, MAX_CHUNK_SIZE_ENV)? {
+            self.max_chunk_size = Some(max_chunk_size);
+        }
+        if let Some(max_attempts) = parse_var(vars, RETRY_MAX_ATTEMPTS_ENV)? {
+            self.retry = Some(self.retry_policy().with_max_attempts(max_attempts));
+        }
+        if let Some(deadline) = parse_var(vars, RETRY_DEADLINE_ENV)? {
+            self.retry = Some(
+                self.retry_policy()
+                    .with_deadline(Duration::from_millis(deadline)),
+            );
+        }
+
+        Ok(self)
+    }
+
+    /// overrides the settings given in the TOML file at `path`
+    #[cfg(feature = "toml")]
+    pub fn load_file(self, path: impl AsRef<Path>) -> Result<Self, EsdmError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| invalid(path.display().to_string(), err.to_string()))?;
+        self.apply_toml(&text, &path.display().to_string())
+    }
+
+    /// overrides the settings given in the TOML file at `path`, needs the
+    /// `toml` feature
+    #[cfg(not(feature = "toml"))]
+    pub fn load_file(self, path: impl AsRef<Path>) -> Result<Self, EsdmError> {
+        Err(invalid(
+            path.as_ref().display().to_string(),
+            "configuration files need the `toml` feature",
+        ))
+    }
+
+    /// overrides the settings given in the TOML document `text`
+    #[cfg(feature = "toml")]
+    pub fn load_toml(self, text: &str) -> Result<Self, EsdmError> {
+        self.apply_toml(text, "TOML")
+    }
+
+    /// errors name `source` and the invalid key
+    #[cfg(feature = "toml")]
+    fn apply_toml(mut self, text: &str, source: &str) -> Result<Self, EsdmError> {
+        let file: file::ClientFile =
+            toml::from_str(text).map_err(|err| invalid(source, err.to_string()))?;
+
+        #[cfg(feature = "native")]
+        {
+            if let Some(dir) = file.socket_dir {
+                self = self.socket_dir(dir);
+            }
+            if file.unpriv_socket.is_some() || file.priv_socket.is_some() {
+                let mut sockets = self.sockets.take().unwrap_or_default();
+                if let Some(path) = file.unpriv_socket {
+                    sockets = sockets.with_unpriv_socket(path);
+                }
+                if let Some(path) = file.priv_socket {
+                    sockets = sockets.with_priv_socket(path);
+                }
+                self.sockets = Some(sockets);
+            }
+        }
+        #[cfg(not(feature = "native"))]
+        {
+            let keys = [
+                ("socket_dir", file.socket_dir.is_some()),
+                ("unpriv_socket", file.unpriv_socket.is_some()),
+                ("priv_socket", file.priv_socket.is_some()),
+            ];
+            if let Some((key, _)) = keys.iter().find(|(_, set)| *set) {
+                return Err(invalid(format!("{source}: {key}"), SOCKETS_NEED_NATIVE));
+            }
+        }
+        if let Some(nodes) = file.max_online_nodes {
+            self.max_online_nodes = Some(nodes);
+        }
+        if let Some(mode) = file.default_mode {
+            self.default_mode = parse_mode(&mode)
+                .ok_or_else(|| invalid(format!("{source}: default_mode"), "unknown mode"))?;
+        }
+        if let Some(max_chunk_size) = file.max_chunk_size {
+            self.max_chunk_size = Some(max_chunk_size);
+        }
+        if let Some(retry) = file.retry {
+            self.retry = Some(retry.apply(self.retry_policy()));
+        }
+
+        Ok(self)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.retry.unwrap_or_else(esdm_retry_policy)
+    }
+
+    fn validate(&self) -> Result<(), EsdmError> {
+        if self.max_online_nodes == Some(0) {
+            return Err(invalid("max_online_nodes", "must be at least 1"));
+        }
+        if self.max_chunk_size == Some(0) {
+            return Err(invalid("max_chunk_size", "must be at least 1"));
+        }
+        if let Some(retry) = &self.retry {
+            retry
+                .validate()
+                .map_err(|reason| invalid("retry", reason))?;
+        }
+        Ok(())
+    }
+
+    /// validates the settings and applies the process-wide ones: socket paths
+    /// (`native` backend) and the number of NUMA nodes
+    ///
+    /// The number of NUMA nodes takes effect once the client library service
+    /// is initialized again, i.e. when no connection is alive.
+    pub fn build(self) -> Result<EsdmClient, EsdmError> {
+        self.validate()?;
+
+        #[cfg(feature = "native")]
+        if let Some(sockets) = &self.sockets {
+            sockets.apply();
+        }
+        if let Some(nodes) = self.max_online_nodes {
+            set_max_online_nodes(nodes);
+        }
+
+        Ok(EsdmClient {
+            default_mode: self.default_mode,
+            retry: self.retry,
+            max_chunk_size: self.max_chunk_size.and_then(NonZeroUsize::new),
+        })
+    }
+}
+
+/// Source of ESDM connections and generators sharing validated settings
+///
+/// Created with [`EsdmClient::builder`], see [`EsdmClientBuilder`] for the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EsdmClient {
+    default_mode: EsdmRngType,
+    retry: Option<RetryPolicy>,
+    max_chunk_size: Option<NonZeroUsize>,
+}
+
+impl Default for EsdmClient {
+    /// fully seeded random numbers, process-wide settings left as they are
+    fn default() -> Self {
+        EsdmClient {
+            default_mode: EsdmRngType::FullySeeded,
+            retry: None,
+            max_chunk_size: None,
+        }
+    }
+}
+
+impl EsdmClient {
+    #[must_use]
+    pub fn builder() -> EsdmClientBuilder {
+        EsdmClientBuilder::default()
+    }
+
+    /// client configured by the environment variables only
+    pub fn from_env() -> Result<Self, EsdmError> {
+        Self::builder().load_env()?.build()
+    }
+
+    #[must_use]
+    pub fn default_mode(&self) -> EsdmRngType {
+        self.default_mode
+    }
+
+    /// retry policy of the handles of this client
+    #[must_use]
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry.unwrap_or_else(esdm_retry_policy)
+    }
+
+    /// connection to the unprivileged service
+    pub fn connection(&self) -> Result<EsdmConnection, EsdmError> {
+        let mut conn = EsdmConnection::new()?;
+        if let Some(retry) = self.retry {
+            conn = conn.with_retry_policy(retry);
+        }
+        if let Some(max_chunk_size) = self.max_chunk_size {
+            conn = conn.with_max_chunk_size(max_chunk_size);
+        }
+        Ok(conn)
+    }
+
+    /// connection to the privileged service (needs root)
+    pub fn priv_connection(&self) -> Result<EsdmPrivConnection, EsdmError> {
+        let mut conn = EsdmPrivConnection::new()?;
+        if let Some(retry) = self.retry {
+            conn = conn.with_retry_policy(retry);
+        }
+        Ok(conn)
+    }
+
+    /// privileged handle, after checking the privileges of the process
+    pub fn admin(&self) -> Result<EsdmAdmin, EsdmError> {
+        EsdmAdmin::connect(|| self.priv_connection())
+    }
+
+    /// generator of the default mode
+    pub fn rng(&self) -> Result<EsdmRng, EsdmError> {
+        self.rng_with_mode(self.default_mode)
+    }
+
+    /// generator of the given mode
+    pub fn rng_with_mode(&self, mode: EsdmRngType) -> Result<EsdmRng, EsdmError> {
+        Ok(self.connection()?.rng(mode))
+    }
+
+    /// buffered generator, see [`BufferedEsdmRng`]
+    pub fn buffered_rng(&self) -> Result<BufferedEsdmRng, EsdmError> {
+        Ok(self.connection()?.buffered_rng())
+    }
+
+    /// need-entropy notification, see [`EsdmNotification`], querying the
+    /// entropy count with the retry policy of this client
+    pub fn notification(&self) -> Result<EsdmNotification, EsdmError> {
+        EsdmNotification::with_connection(self.connection()?)
+    }
+}
+
+#[cfg(feature = "toml")]
+mod file {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::RetryPolicy;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct ClientFile {
+        pub(super) socket_dir: Option<PathBuf>,
+        pub(super) unpriv_socket: Option<PathBuf>,
+        pub(super) priv_socket: Option<PathBuf>,
+        pub(super) max_online_nodes: Option<u32>,
+        pub(super) default_mode: Option<String>,
+        pub(super) max_chunk_size: Option<usize>,
+        pub(super) retry: Option<RetryFile>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct RetryFile {
+        max_attempts: Option<u32>,
+        initial_backoff_ms: Option<u64>,
+        max_backoff_ms: Option<u64>,
+        jitter: Option<bool>,
+        deadline_ms: Option<u64>,
+    }
+
+    impl RetryFile {
+        /// `policy` with the settings given in the file
+        pub(super) fn apply(self, mut policy: RetryPolicy) -> RetryPolicy {
+            if let Some(max_attempts) = self.max_attempts {
+                policy = policy.with_max_attempts(max_attempts);
+            }
+            let initial = self
+                .initial_backoff_ms
+                .map_or(policy.initial_backoff(), Duration::from_millis);
+            let max = self
+                .max_backoff_ms
+                .map_or(policy.max_backoff(), Duration::from_millis);
+            policy = policy.with_backoff(initial, max);
+            if let Some(jitter) = self.jitter {
+                policy = policy.with_jitter(jitter);
+            }
+            if let Some(deadline) = self.deadline_ms {
+                policy = policy.with_deadline(Duration::from_millis(deadline));
+            }
+            policy
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: Vec<(String, OsString)> = vars
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).into()))
+            .collect();
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    #[test]
+    fn test_load_vars() {
+        let builder = EsdmClient::builder()
+            .max_online_nodes(4)
+            .load_vars(&vars(&[
+                (SOCKET_DIR_ENV, ""),
+                (DEFAULT_MODE_ENV, "pr"),
+                (MAX_CHUNK_SIZE_ENV, ""),
+                (RETRY_MAX_ATTEMPTS_ENV, "2"),
+                (RETRY_DEADLINE_ENV, "1500"),
+            ]))
+            .unwrap();
+        assert_eq!(builder.max_online_nodes, Some(4));
+        assert_eq!(builder.default_mode, EsdmRngType::PredictionResistant);
+        assert_eq!(builder.max_chunk_size, None);
+        let retry = builder.retry.unwrap();
+        assert_eq!(retry.max_attempts(), 2);
+        assert_eq!(retry.deadline(), Some(Duration::from_millis(1500)));
+
+        let err = EsdmClient::builder()
+            .load_vars(&vars(&[(MAX_ONLINE_NODES_ENV, "many")]))
+            .unwrap_err();
+        assert!(
+            matches!(err, EsdmError::InvalidConfig { ref setting, .. } if setting == MAX_ONLINE_NODES_ENV)
+        );
+        assert!(
+            EsdmClient::builder()
+                .load_vars(&vars(&[(DEFAULT_MODE_ENV, "fast")]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let invalid_setting = |builder: EsdmClientBuilder| match builder.build() {
+            Err(EsdmError::InvalidConfig { setting, .. }) => setting,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        assert_eq!(
+            invalid_setting(EsdmClient::builder().max_online_nodes(0)),
+            "max_online_nodes"
+        );
+        assert_eq!(
+            invalid_setting(EsdmClient::builder().max_chunk_size(0)),
+            "max_chunk_size"
+        );
+        assert_eq!(
+            invalid_setting(EsdmClient::builder().retry(
+                RetryPolicy::new().with_backoff(Duration::from_secs(2), Duration::from_secs(1))
+            )),
+            "retry"
+        );
+
+        let client = EsdmClient::builder()
+            .default_mode(EsdmRngType::MinimallySeeded)
+            .build()
+            .unwrap();
+        assert_eq!(client.default_mode(), EsdmRngType::MinimallySeeded);
+        assert_eq!(client.retry_policy(), esdm_retry_policy());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_load_toml() {
+        let builder = EsdmClient::builder()
+            .load_toml(
+                r#"
+                default_mode = "non-blocking"
+                max_chunk_size = 4096
+
+                [retry]
+                max_attempts = 3
+                max_backoff_ms = 500
+                jitter = false
+                "#,
+            )
+            .unwrap();
+        assert_eq!(builder.default_mode, EsdmRngType::NonBlocking);
+        assert_eq!(builder.max_chunk_size, Some(4096));
+        let retry = builder.retry.unwrap();
+        assert_eq!(retry.max_attempts(), 3);
+        assert_eq!(retry.max_backoff(), Duration::from_millis(500));
+        assert!(!retry.jitter());
+
+        assert!(EsdmClient::builder().load_toml("max_nodes = 2").is_err());
+        assert!(
+            EsdmClient::builder()
+                .load_toml("default_mode = \"fast\"")
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_socket_settings() {
+        let builder = EsdmClient::builder()
+            .load_vars(&vars(&[(SOCKET_DIR_ENV, "/run/esdm-test")]))
+            .unwrap();
+        assert_eq!(
+            builder.sockets,
+            Some(EsdmClientConfig::new().with_socket_dir("/run/esdm-test"))
+        );
+
+        #[cfg(feature = "toml")]
+        {
+            let builder = builder
+                .load_toml("priv_socket = \"/srv/esdm-priv\"")
+                .unwrap();
+            let sockets = builder.sockets.unwrap();
+            assert_eq!(
+                sockets.unpriv_socket(),
+                Path::new("/run/esdm-test/esdm-rpc-unpriv")
+            );
+            assert_eq!(sockets.priv_socket(), Path::new("/srv/esdm-priv"));
+        }
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_socket_settings() {
+        let err = EsdmClient::builder()
+            .load_vars(&vars(&[(SOCKET_DIR_ENV, "/run/esdm-test")]))
+            .unwrap_err();
+        assert!(
+            matches!(err, EsdmError::InvalidConfig { ref setting, .. } if setting == SOCKET_DIR_ENV)
+        );
+
+        #[cfg(feature = "toml")]
+        assert!(
+            EsdmClient::builder()
+                .load_toml("priv_socket = \"/srv/esdm-priv\"")
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_client_handles() {
+        use crate::mock::MockEsdmServer;
+        use rand_core::TryRng;
+
+        let server = MockEsdmServer::start().unwrap();
+        let retry = RetryPolicy::fail_fast();
+        let client = EsdmClient::builder()
+            .default_mode(EsdmRngType::PredictionResistant)
+            .retry(retry)
+            .max_chunk_size(16)
+            .build()
+            .unwrap();
+
+        let conn = client.connection().unwrap();
+        assert_eq!(conn.retry_policy(), retry);
+        assert_eq!(conn.max_chunk_size(), 16);
+        assert_eq!(client.notification().unwrap().conn.retry_policy(), retry);
+
+        let mut rng = client.rng().unwrap();
+        assert_eq!(rng.rng_type(), EsdmRngType::PredictionResistant);
+        let mut buf = [0u8; 64];
+        rng.try_fill_bytes(&mut buf).unwrap();
+        assert_eq!(
+            server.calls(crate::EsdmOperation::GetRandomBytesPr),
+            buf.len() as u64 / 16
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Location of the ESDM services
+//!
+//! By default, the client talks to the sockets of the system's esdm-server in
+//! `/var/run`. Containers with bind-mounted sockets, chroots or test setups
+//! running several ESDM instances point the client elsewhere with an
+//! [`EsdmClientConfig`], either explicitly or from the [`SOCKET_DIR_ENV`]
+//! environment variable.
+//!
+//! The client library state is process-wide, so is the applied configuration:
+//! it is used by every connection and generator of the process.
+//!
+//! [`EsdmClientConfig`] needs the `native` backend. The C client libraries of
+//! the `ffi` backend always connect to their compiled-in sockets and find the
+//! need-entropy notification in a System V shared memory segment and
+//! semaphore of the ESDM server, neither of which can be moved. The `native`
+//! backend emulates the notification by polling the unprivileged service, so
+//! it follows the unprivileged socket and needs no further settings.
+
+#[cfg(feature = "native")]
+use std::ffi::OsString;
+#[cfg(feature = "native")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "native")]
+use std::sync::{PoisonError, RwLock};
+
+#[cfg(feature = "native")]
+use crate::backend;
+
+/// environment variable naming the directory of the ESDM sockets, only
+/// supported by the `native` backend
+pub const SOCKET_DIR_ENV: &str = "ESDM_SOCKET_DIR";
+
+#[cfg(feature = "native")]
+static CURRENT: RwLock<EsdmClientConfig> = RwLock::new(EsdmClientConfig::new());
+
+/// Socket paths of the ESDM services, applied process-wide
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct EsdmClientConfig {
+    unpriv_socket: Option<PathBuf>,
+    priv_socket: Option<PathBuf>,
+}
+
+#[cfg(feature = "native")]
+fn file_name(path: &str) -> &Path {
+    Path::new(Path::new(path).file_name().unwrap())
+}
+
+#[cfg(feature = "native")]
+impl EsdmClientConfig {
+    /// the compiled-in socket paths of the system's esdm-server
+    #[must_use]
+    pub const fn new() -> Self {
+        EsdmClientConfig {
+            unpriv_socket: None,
+            priv_socket: None,
+        }
+    }
+
+    /// sockets in the directory named by [`SOCKET_DIR_ENV`], the default ones
+    /// if it is unset or empty
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::from_socket_dir_var(std::env::var_os(SOCKET_DIR_ENV))
+    }
+
+    fn from_socket_dir_var(dir: Option<OsString>) -> Self {
+        match dir {
+            Some(dir) if !dir.is_empty() => Self::new().with_socket_dir(dir),
+            _ => Self::new(),
+        }
+    }
+
+    /// uses the sockets with their default names in `dir`
+    #[must_use]
+    pub fn with_socket_dir(self, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        self.with_unpriv_socket(dir.join(file_name(backend::UNPRIV_SOCKET)))
+            .with_priv_socket(dir.join(file_name(backend::PRIV_SOCKET)))
+    }
+
+    /// socket of the unprivileged service
+    #[must_use]
+    pub fn with_unpriv_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.unpriv_socket = Some(path.into());
+        self
+    }
+
+    /// socket of the privileged service
+    #[must_use]
+    pub fn with_priv_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.priv_socket = Some(path.into());
+        self
+    }
+
+    #[must_use]
+    pub fn unpriv_socket(&self) -> &Path {
+        self.unpriv_socket
+            .as_deref()
+            .unwrap_or(Path::new(backend::UNPRIV_SOCKET))
+    }
+
+    #[must_use]
+    pub fn priv_socket(&self) -> &Path {
+        self.priv_socket
+            .as_deref()
+            .unwrap_or(Path::new(backend::PRIV_SOCKET))
+    }
+
+    /// true, if the compiled-in socket paths are used
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        self.unpriv_socket() == Path::new(backend::UNPRIV_SOCKET)
+            && self.priv_socket() == Path::new(backend::PRIV_SOCKET)
+    }
+
+    /// uses this configuration for all connections of the process, including
+    /// those already established
+    pub fn apply(&self) {
+        let paths =
+            (!self.is_default()).then(|| (self.unpriv_socket().into(), self.priv_socket().into()));
+        backend::set_socket_paths(paths);
+
+        *CURRENT.write().unwrap_or_else(PoisonError::into_inner) = self.clone();
+    }
+
+    /// the configuration applied last
+    #[must_use]
+    pub fn current() -> Self {
+        CURRENT
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_paths() {
+        let config = EsdmClientConfig::new();
+        assert!(config.is_default());
+        assert_eq!(
+            config.unpriv_socket(),
+            Path::new("/var/run/esdm-rpc-unpriv")
+        );
+        assert_eq!(EsdmClientConfig::from_socket_dir_var(None), config);
+        assert_eq!(
+            EsdmClientConfig::from_socket_dir_var(Some(OsString::new())),
+            config
+        );
+
+        let config = EsdmClientConfig::from_socket_dir_var(Some("/run/esdm-test".into()));
+        assert!(!config.is_default());
+        assert_eq!(
+            config.unpriv_socket(),
+            Path::new("/run/esdm-test/esdm-rpc-unpriv")
+        );
+        assert_eq!(
+            config.priv_socket(),
+            Path::new("/run/esdm-test/esdm-rpc-priv")
+        );
+
+        let config = config.with_priv_socket("/srv/priv");
+        assert_eq!(config.priv_socket(), Path::new("/srv/priv"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_apply() {
+        use crate::EsdmConnection;
+        use crate::mock::MockEsdmServer;
+
+        let server = MockEsdmServer::start().unwrap();
+        assert_eq!(
+            EsdmClientConfig::current(),
+            EsdmClientConfig::new().with_socket_dir(server.socket_dir())
+        );
+        let conn = EsdmConnection::new().unwrap();
+
+        EsdmClientConfig::new()
+            .with_socket_dir(server.socket_dir().join("missing"))
+            .apply();
+        assert!(conn.entropy_count().unwrap_err().is_unreachable());
+
+        let config = EsdmClientConfig::new().with_socket_dir(server.socket_dir());
+        config.apply();
+        assert_eq!(EsdmClientConfig::current(), config);
+        conn.entropy_count().unwrap();
+
+        drop(conn);
+    }
+}
@@ -0,0 +1,676 @@
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{
+    BufferedEsdmRng, CancellationToken, EntropyCredit, EsdmError, EsdmOperation, EsdmRng,
+    EsdmRngType, EsdmSeedLevel, EsdmService, EsdmStatus, RetryClass, RetryPolicy, SeedFlags,
+    SeedInfo, backend, cancel, esdm_retry, esdm_retry_policy, esdm_status_text, fork, seed,
+};
+
+/// first and maximum delay between two status polls of [`EsdmConnection::wait_for_seed_level`]
+const SEED_WAIT_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const SEED_WAIT_MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// state of one ESDM client library service in this process
+#[derive(Debug)]
+struct ServiceState {
+    // number of handles holding a reference on the service
+    refs: u32,
+    // fork generation the client library state was set up in
+    generation: u64,
+}
+
+impl ServiceState {
+    const fn new() -> Self {
+        ServiceState {
+            refs: 0,
+            generation: 0,
+        }
+    }
+}
+
+/*
+ * The ESDM client libraries keep one process global state per service,
+ * these track how many handles are alive for each of them.
+ */
+static LIB_MUTEX_UNPRIV: Mutex<ServiceState> = Mutex::new(ServiceState::new());
+static LIB_MUTEX_PRIV: Mutex<ServiceState> = Mutex::new(ServiceState::new());
+static LIB_MUTEX_AUX: Mutex<ServiceState> = Mutex::new(ServiceState::new());
+
+fn init_service(service: EsdmService) -> Result<(), EsdmError> {
+    let (op, init): (_, fn() -> i32) = match service {
+        EsdmService::Unprivileged => (
+            EsdmOperation::InitUnprivService,
+            backend::init_unpriv_service,
+        ),
+        EsdmService::Privileged => (EsdmOperation::InitPrivService, backend::init_priv_service),
+        EsdmService::Aux => (
+            EsdmOperation::InitAux,
+            backend::aux_init_wait_for_need_entropy,
+        ),
+    };
+
+    esdm_retry(op, &esdm_retry_policy(), init)
+}
+
+fn fini_service(service: EsdmService) {
+    match service {
+        EsdmService::Unprivileged => backend::fini_unpriv_service(),
+        EsdmService::Privileged => backend::fini_priv_service(),
+        EsdmService::Aux => backend::aux_fini_wait_for_need_entropy(),
+    }
+}
+
+/// reference on one of the ESDM client library services, released on drop
+#[derive(Debug)]
+pub(crate) struct ServiceRef {
+    service: EsdmService,
+    // fork generation this reference was taken in
+    generation: AtomicU64,
+}
+
+impl ServiceRef {
+    /// locks the service state, resets it in a forked child
+    fn lock(service: EsdmService) -> MutexGuard<'static, ServiceState> {
+        let mut state = match service {
+            EsdmService::Unprivileged => &LIB_MUTEX_UNPRIV,
+            EsdmService::Privileged => &LIB_MUTEX_PRIV,
+            EsdmService::Aux => &LIB_MUTEX_AUX,
+        }
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+        let generation = fork::generation();
+        if state.generation != generation {
+            // the references and the client library state belong to the parent,
+            // release the inherited library state (e.g. socket copies) in this process
+            if state.refs > 0 {
+                fini_service(service);
+            }
+            state.refs = 0;
+            state.generation = generation;
+        }
+
+        state
+    }
+
+    /// adds a reference, initializes the service for the first one (retried
+    /// according to the global retry policy)
+    fn register(service: EsdmService, state: &mut ServiceState) -> Result<(), EsdmError> {
+        if state.refs == 0 {
+            init_service(service)?;
+        }
+
+        state.refs += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn acquire(service: EsdmService) -> Result<Self, EsdmError> {
+        let mut state = Self::lock(service);
+        Self::register(service, &mut state)?;
+
+        Ok(ServiceRef {
+            service,
+            generation: AtomicU64::new(state.generation),
+        })
+    }
+
+    /// re-initializes the service for a reference inherited from the parent process
+    pub(crate) fn ensure_current(&self) -> Result<(), EsdmError> {
+        if self.generation.load(Ordering::SeqCst) == fork::generation() {
+            return Ok(());
+        }
+
+        let mut state = Self::lock(self.service);
+        if self.generation.load(Ordering::SeqCst) != state.generation {
+            Self::register(self.service, &mut state)?;
+            self.generation.store(state.generation, Ordering::SeqCst);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ServiceRef {
+    fn drop(&mut self) {
+        let mut state = Self::lock(self.service);
+
+        // references of the parent process were dropped with its state
+        if *self.generation.get_mut() != state.generation {
+            return;
+        }
+
+        if state.refs == 1 {
+            fini_service(self.service);
+        }
+
+        state.refs -= 1;
+    }
+}
+
+/// Handle on the unprivileged ESDM service
+///
+/// The service stays initialized as long as any handle (or [`EsdmRng`] created
+/// from it) is alive. Cloning is cheap, clones can be moved to other threads.
+/// Handles inherited by a forked child re-initialize the service on first use.
+#[derive(Debug, Clone)]
+pub struct EsdmConnection {
+    service: Arc<ServiceRef>,
+    retry_policy: Option<RetryPolicy>,
+    max_chunk_size: usize,
+    cancellation: Option<CancellationToken>,
+    // set once ESDM reported to be seeded, shared by clones
+    seeded: Arc<AtomicBool>,
+}
+
+impl EsdmConnection {
+    pub fn new() -> Result<Self, EsdmError> {
+        Ok(EsdmConnection {
+            service: Arc::new(ServiceRef::acquire(EsdmService::Unprivileged)?),
+            retry_policy: None,
+            max_chunk_size: backend::MAX_CHUNK_SIZE,
+            cancellation: None,
+            seeded: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// returns a handle using `policy` instead of the global retry policy
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// the retry policy used by this handle
+    #[must_use]
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.unwrap_or_else(esdm_retry_policy)
+    }
+
+    /// returns a handle, whose calls stop once `token` is cancelled
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// the cancellation token observed by this handle
+    #[must_use]
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// prepares a call: re-initializes the service after a fork and makes the
+    /// cancellation token of this handle observable until the scope is dropped
+    pub(crate) fn enter(&self) -> Result<cancel::Scope, EsdmError> {
+        self.service.ensure_current()?;
+        Ok(cancel::enter(self.cancellation.as_ref()))
+    }
+
+    /// returns a handle requesting at most `max_chunk_size` random bytes per
+    /// RPC, larger requests are split
+    #[must_use]
+    pub fn with_max_chunk_size(mut self, max_chunk_size: NonZeroUsize) -> Self {
+        self.max_chunk_size = max_chunk_size.get();
+        self
+    }
+
+    /// maximum number of random bytes requested per RPC, defaults to a size
+    /// every ESDM server accepts
+    #[must_use]
+    pub fn max_chunk_size(&self) -> usize {
+        self.max_chunk_size
+    }
+
+    /// returns a random number generator of the given type using this connection
+    #[must_use]
+    pub fn rng(&self, rng_type: EsdmRngType) -> EsdmRng {
+        EsdmRng::with_connection(self.clone(), rng_type)
+    }
+
+    /// returns a buffered random number generator using this connection
+    #[must_use]
+    pub fn buffered_rng(&self) -> BufferedEsdmRng {
+        BufferedEsdmRng::with_connection(self.clone())
+    }
+
+    /// fills `dst` completely with random bytes of the given type, in chunks of
+    /// at most [`max_chunk_size`](Self::max_chunk_size) bytes
+    pub fn fill_bytes(&self, rng_type: EsdmRngType, dst: &mut [u8]) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        rng_type.fill_bytes(&self.retry_policy(), self.max_chunk_size, dst)
+    }
+
+    /// fills `dst` like [`fill_bytes`](Self::fill_bytes), but fails with
+    /// [`EsdmError::NotSeeded`] in [`EsdmRngType::NonBlocking`] mode as long as
+    /// ESDM is not seeded, so that the output of the crypto RNG types is always
+    /// seeded
+    ///
+    /// The seed level is queried until ESDM reported to be seeded once, later
+    /// fills of the handle and its clones cost no extra RPC. ESDM does not
+    /// become unseeded again while it runs, so a seeded answer also holds for
+    /// the request following it.
+    pub(crate) fn fill_seeded_bytes(
+        &self,
+        rng_type: EsdmRngType,
+        dst: &mut [u8],
+    ) -> Result<(), EsdmError> {
+        if rng_type == EsdmRngType::NonBlocking && !self.seeded.load(Ordering::Relaxed) {
+            let current = self.seed_level()?;
+            if current == EsdmSeedLevel::Unseeded {
+                return Err(EsdmError::NotSeeded {
+                    current,
+                    required: EsdmSeedLevel::MinimallySeeded,
+                });
+            }
+            self.seeded.store(true, Ordering::Relaxed);
+        }
+
+        self.fill_bytes(rng_type, dst)
+    }
+
+    /// fills `dst` like [`fill_bytes`](Self::fill_bytes), returns the seed level
+    /// of ESDM before the bytes were generated
+    ///
+    /// Mostly useful with [`EsdmRngType::NonBlocking`], whose output is only as
+    /// good as the seeding at the time of the request. The seed level is
+    /// queried right before the request.
+    pub fn fill_bytes_with_seed_level(
+        &self,
+        rng_type: EsdmRngType,
+        dst: &mut [u8],
+    ) -> Result<EsdmSeedLevel, EsdmError> {
+        let seed_level = self.seed_level()?;
+        self.fill_bytes(rng_type, dst)?;
+
+        Ok(seed_level)
+    }
+
+    /// fills `buf` with seed data from the entropy sources, see [`SeedFlags`]
+    /// for blocking and non-blocking requests
+    ///
+    /// The seed data is moved to the start of `buf`, the rest is zeroed. Fails
+    /// with [`EsdmError::BufferTooSmall`] naming the needed size, if `buf` is too
+    /// small for the data of all entropy sources.
+    pub fn get_seed(&self, buf: &mut [u8], flags: SeedFlags) -> Result<SeedInfo, EsdmError> {
+        let _scope = self.enter()?;
+        let mut policy = self.retry_policy();
+        if flags.contains(SeedFlags::NON_BLOCKING) {
+            // EAGAIN signals missing entropy here, the caller asked not to wait for it
+            policy = policy.with_retryable(RetryClass::Transient, false);
+        }
+
+        policy.run(|attempts| {
+            let ret = backend::get_seed(buf, flags.bits());
+            seed::seed_info(buf, ret, attempts)
+        })
+    }
+
+    /// mixes data into ESDM without crediting entropy
+    pub fn write_data(&self, data: &[u8]) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(EsdmOperation::WriteData, &self.retry_policy(), || {
+            backend::write_data(data)
+        })
+    }
+
+    /// entropy count of the auxiliary pool in bits
+    pub fn entropy_count(&self) -> Result<u32, EsdmError> {
+        let _scope = self.enter()?;
+        let mut ent_cnt: u32 = 0;
+        esdm_retry(EsdmOperation::GetEntropyCount, &self.retry_policy(), || {
+            backend::rnd_get_ent_cnt(&mut ent_cnt)
+        })?;
+
+        Ok(ent_cnt)
+    }
+
+    pub fn write_wakeup_thresh(&self) -> Result<u32, EsdmError> {
+        let _scope = self.enter()?;
+        let mut write_wakeup_thresh: u32 = 0;
+        esdm_retry(
+            EsdmOperation::GetWriteWakeupThresh,
+            &self.retry_policy(),
+            || backend::get_write_wakeup_thresh(&mut write_wakeup_thresh),
+        )?;
+
+        Ok(write_wakeup_thresh)
+    }
+
+    /// size of the entropy pool of ESDM in bits
+    pub fn pool_size(&self) -> Result<u32, EsdmError> {
+        let _scope = self.enter()?;
+        let mut pool_size: u32 = 0;
+        esdm_retry(EsdmOperation::GetPoolSize, &self.retry_policy(), || {
+            backend::get_poolsize(&mut pool_size)
+        })?;
+
+        Ok(pool_size)
+    }
+
+    /// minimum number of seconds between two reseeds of the ESDM DRNG
+    pub fn min_reseed_secs(&self) -> Result<u32, EsdmError> {
+        let _scope = self.enter()?;
+        let mut min_reseed_secs: u32 = 0;
+        esdm_retry(
+            EsdmOperation::GetMinReseedSecs,
+            &self.retry_policy(),
+            || backend::get_min_reseed_secs(&mut min_reseed_secs),
+        )?;
+
+        Ok(min_reseed_secs)
+    }
+
+    pub fn jent_status_str(&self) -> Result<String, EsdmError> {
+        let _scope = self.enter()?;
+        esdm_status_text(
+            EsdmOperation::JentStatus,
+            &self.retry_policy(),
+            backend::jent_status,
+        )
+    }
+
+    pub fn status_str(&self) -> Result<String, EsdmError> {
+        let _scope = self.enter()?;
+        esdm_status_text(EsdmOperation::Status, &self.retry_policy(), backend::status)
+    }
+
+    /// returns the parsed status of ESDM
+    pub fn status(&self) -> Result<EsdmStatus, EsdmError> {
+        Ok(EsdmStatus::parse(&self.status_str()?))
+    }
+
+    pub fn seed_level(&self) -> Result<EsdmSeedLevel, EsdmError> {
+        self.status()?.seed_level().ok_or(EsdmError::InvalidStatus {
+            op: EsdmOperation::Status,
+            reason: "missing seeding state",
+        })
+    }
+
+    pub fn is_fully_seeded(&self) -> Result<bool, EsdmError> {
+        Ok(self.seed_level()? == EsdmSeedLevel::FullySeeded)
+    }
+
+    /// waits until ESDM reached at least `level`, returns the reached level
+    ///
+    /// Polls the status with a backoff from 10 ms up to 1 s. Once `deadline`
+    /// passed, fails with [`EsdmError::NotSeeded`] if ESDM answered with a lower
+    /// level, or with the last error, for which
+    /// [`is_unreachable`](EsdmError::is_unreachable) is true, if ESDM could not be
+    /// reached. Other errors are returned at once. Fails with
+    /// [`EsdmError::Cancelled`] once the observed [`CancellationToken`] is cancelled.
+    pub fn wait_for_seed_level(
+        &self,
+        level: EsdmSeedLevel,
+        deadline: Instant,
+    ) -> Result<EsdmSeedLevel, EsdmError> {
+        let _scope = self.enter()?;
+        // the polling is the retry loop here, a single poll must not wait on its own
+        let conn = self.clone().with_retry_policy(RetryPolicy::fail_fast());
+        let cancelled = EsdmError::Cancelled {
+            op: EsdmOperation::Status,
+        };
+        let mut backoff = SEED_WAIT_INITIAL_BACKOFF;
+
+        loop {
+            if cancel::is_cancelled() {
+                return Err(cancelled);
+            }
+
+            let err = match conn.seed_level() {
+                Ok(current) if current >= level => return Ok(current),
+                Ok(current) => EsdmError::NotSeeded {
+                    current,
+                    required: level,
+                },
+                Err(err) if err.retry_class() == RetryClass::Fatal => return Err(err),
+                Err(err) => err,
+            };
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(err);
+            }
+            if cancel::sleep(backoff.min(left)) {
+                return Err(cancelled);
+            }
+            backoff = backoff.saturating_mul(2).min(SEED_WAIT_MAX_BACKOFF);
+        }
+    }
+
+    /// overall entropy level of ESDM in bits
+    pub fn entropy_level(&self) -> Result<u32, EsdmError> {
+        let _scope = self.enter()?;
+        let mut entropy_level: u32 = 0;
+        esdm_retry(EsdmOperation::GetEntropyLevel, &self.retry_policy(), || {
+            backend::get_ent_lvl(&mut entropy_level)
+        })?;
+
+        Ok(entropy_level)
+    }
+}
+
+/// Handle on the privileged ESDM service (needs root), also usable as [`EsdmConnection`]
+///
+/// The privileged operations are only reachable through this handle.
+#[derive(Debug, Clone)]
+pub struct EsdmPrivConnection {
+    unpriv: EsdmConnection,
+    service: Arc<ServiceRef>,
+}
+
+impl EsdmPrivConnection {
+    pub fn new() -> Result<Self, EsdmError> {
+        let unpriv = EsdmConnection::new()?;
+
+        Ok(EsdmPrivConnection {
+            unpriv,
+            service: Arc::new(ServiceRef::acquire(EsdmService::Privileged)?),
+        })
+    }
+
+    /// returns a handle using `policy` instead of the global retry policy
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.unpriv = self.unpriv.with_retry_policy(policy);
+        self
+    }
+
+    /// returns a handle, whose calls stop once `token` is cancelled
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.unpriv = self.unpriv.with_cancellation(token);
+        self
+    }
+
+    /// the unprivileged connection held by this handle
+    #[must_use]
+    pub fn unprivileged(&self) -> &EsdmConnection {
+        &self.unpriv
+    }
+
+    fn enter(&self) -> Result<cancel::Scope, EsdmError> {
+        self.service.ensure_current()?;
+        Ok(cancel::enter(self.unpriv.cancellation.as_ref()))
+    }
+
+    /// adds the data of `credit` to the auxiliary pool, crediting its bits of entropy
+    pub fn add_entropy(&self, credit: EntropyCredit<'_>) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(EsdmOperation::AddEntropy, &self.retry_policy(), || {
+            backend::rnd_add_entropy(credit.data(), credit.bits())
+        })
+    }
+
+    /// increases the entropy count of the auxiliary pool by `entropy_increment` bits
+    pub fn add_to_entropy_count(&self, entropy_increment: u32) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(
+            EsdmOperation::AddToEntropyCount,
+            &self.retry_policy(),
+            || backend::rnd_add_to_ent_cnt(entropy_increment),
+        )
+    }
+
+    /// forces a reseed of the DRNG
+    pub fn reseed_crng(&self) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(
+            EsdmOperation::ReseedCrng,
+            &self.retry_policy(),
+            backend::rnd_reseed_crng,
+        )
+    }
+
+    /// clears the entropy count of the auxiliary pool
+    pub fn clear_pool(&self) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(
+            EsdmOperation::ClearPool,
+            &self.retry_policy(),
+            backend::rnd_clear_pool,
+        )
+    }
+
+    /// sets the entropy count in bits below which writers of entropy are woken up
+    pub fn set_write_wakeup_thresh(&self, write_wakeup_thresh: u32) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(
+            EsdmOperation::SetWriteWakeupThresh,
+            &self.retry_policy(),
+            || backend::set_write_wakeup_thresh(write_wakeup_thresh),
+        )
+    }
+
+    /// sets the minimum number of seconds between two reseeds of the ESDM DRNG
+    pub fn set_min_reseed_secs(&self, min_reseed_secs: u32) -> Result<(), EsdmError> {
+        let _scope = self.enter()?;
+        esdm_retry(
+            EsdmOperation::SetMinReseedSecs,
+            &self.retry_policy(),
+            || backend::set_min_reseed_secs(min_reseed_secs),
+        )
+    }
+}
+
+impl Deref for EsdmPrivConnection {
+    type Target = EsdmConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.unpriv
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEsdmServer;
+
+    #[test]
+    fn test_wait_for_seed_level() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmConnection::new().unwrap();
+        let soon = || Instant::now() + Duration::from_millis(50);
+
+        assert_eq!(
+            conn.wait_for_seed_level(EsdmSeedLevel::FullySeeded, soon())
+                .unwrap(),
+            EsdmSeedLevel::FullySeeded
+        );
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        assert_eq!(
+            conn.wait_for_seed_level(EsdmSeedLevel::MinimallySeeded, soon())
+                .unwrap(),
+            EsdmSeedLevel::MinimallySeeded
+        );
+        let err = conn
+            .wait_for_seed_level(EsdmSeedLevel::FullySeeded, soon())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::NotSeeded {
+                current: EsdmSeedLevel::MinimallySeeded,
+                required: EsdmSeedLevel::FullySeeded
+            }
+        ));
+        assert!(!err.is_unreachable());
+
+        server.set_reachable(false);
+        let err = conn
+            .wait_for_seed_level(EsdmSeedLevel::FullySeeded, soon())
+            .unwrap_err();
+        assert!(err.is_unreachable());
+
+        // returns as soon as ESDM gets seeded
+        server.set_reachable(true);
+        let waiter = {
+            let conn = conn.clone();
+            std::thread::spawn(move || {
+                conn.wait_for_seed_level(
+                    EsdmSeedLevel::FullySeeded,
+                    Instant::now() + Duration::from_secs(10),
+                )
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        server.set_seed_level(EsdmSeedLevel::FullySeeded);
+        assert_eq!(waiter.join().unwrap().unwrap(), EsdmSeedLevel::FullySeeded);
+    }
+
+    #[test]
+    fn test_wait_for_seed_level_cancelled() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+
+        let token = CancellationToken::new();
+        let conn = EsdmConnection::new()
+            .unwrap()
+            .with_cancellation(token.clone());
+        let waiter = std::thread::spawn(move || {
+            conn.wait_for_seed_level(
+                EsdmSeedLevel::MinimallySeeded,
+                Instant::now() + Duration::from_secs(30),
+            )
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        token.cancel();
+        let err = waiter.join().unwrap().unwrap_err();
+        assert!(matches!(err, EsdmError::Cancelled { .. }));
+        assert_eq!(err.errno(), Some(libc::ECANCELED));
+    }
+
+    #[test]
+    fn test_cancel_blocking_rpc() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_latency(Duration::from_secs(2));
+
+        let token = CancellationToken::new();
+        let conn = EsdmConnection::new().unwrap();
+        let caller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                let result = token.run(|| conn.entropy_count());
+                (result, start.elapsed())
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        token.cancel();
+        let (result, elapsed) = caller.join().unwrap();
+        assert!(matches!(
+            result,
+            Err(EsdmError::Cancelled {
+                op: EsdmOperation::GetEntropyCount
+            })
+        ));
+        assert!(elapsed < Duration::from_secs(1));
+        server.set_latency(Duration::ZERO);
+    }
+}
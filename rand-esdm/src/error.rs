@@ -0,0 +1,429 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{EsdmSeedLevel, RetryClass};
+
+/// ESDM service an operation is issued against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EsdmService {
+    /// unprivileged RPC service (random bytes, status, write data, ...)
+    Unprivileged,
+
+    /// privileged RPC service (entropy accounting, pool management), needs root
+    Privileged,
+
+    /// auxiliary client used for need-entropy notifications
+    Aux,
+}
+
+impl fmt::Display for EsdmService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EsdmService::Unprivileged => f.write_str("unprivileged"),
+            EsdmService::Privileged => f.write_str("privileged"),
+            EsdmService::Aux => f.write_str("aux"),
+        }
+    }
+}
+
+/// privilege the caller lacks for the privileged ESDM service
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MissingPrivilege {
+    /// esdm-server only serves privileged calls of processes with `CAP_SYS_ADMIN`
+    CapSysAdmin,
+
+    /// the socket of the privileged service cannot be opened for reading and writing
+    SocketAccess { path: PathBuf },
+}
+
+impl fmt::Display for MissingPrivilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingPrivilege::CapSysAdmin => f.write_str("CAP_SYS_ADMIN"),
+            MissingPrivilege::SocketAccess { path } => {
+                write!(f, "read and write access to {}", path.display())
+            }
+        }
+    }
+}
+
+/// ESDM client operation, named after the underlying client library call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EsdmOperation {
+    InitUnprivService,
+    InitPrivService,
+    InitAux,
+    GetRandomBytesFull,
+    GetRandomBytesPr,
+    GetRandomBytesMin,
+    GetRandomBytes,
+    GetSeed,
+    WriteData,
+    GetEntropyCount,
+    AddEntropy,
+    AddToEntropyCount,
+    ReseedCrng,
+    ClearPool,
+    GetWriteWakeupThresh,
+    SetWriteWakeupThresh,
+    GetMinReseedSecs,
+    SetMinReseedSecs,
+    GetPoolSize,
+    GetEntropyLevel,
+    Status,
+    JentStatus,
+    WaitForNeedEntropy,
+}
+
+impl EsdmOperation {
+    /// name of the ESDM client library function implementing this operation
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            EsdmOperation::InitUnprivService => "esdm_rpcc_init_unpriv_service",
+            EsdmOperation::InitPrivService => "esdm_rpcc_init_priv_service",
+            EsdmOperation::InitAux => "esdm_aux_init_wait_for_need_entropy",
+            EsdmOperation::GetRandomBytesFull => "esdm_rpcc_get_random_bytes_full",
+            EsdmOperation::GetRandomBytesPr => "esdm_rpcc_get_random_bytes_pr",
+            EsdmOperation::GetRandomBytesMin => "esdm_rpcc_get_random_bytes_min",
+            EsdmOperation::GetRandomBytes => "esdm_rpcc_get_random_bytes",
+            EsdmOperation::GetSeed => "esdm_rpcc_get_seed",
+            EsdmOperation::WriteData => "esdm_rpcc_write_data",
+            EsdmOperation::GetEntropyCount => "esdm_rpcc_rnd_get_ent_cnt",
+            EsdmOperation::AddEntropy => "esdm_rpcc_rnd_add_entropy",
+            EsdmOperation::AddToEntropyCount => "esdm_rpcc_rnd_add_to_ent_cnt",
+            EsdmOperation::ReseedCrng => "esdm_rpcc_rnd_reseed_crng",
+            EsdmOperation::ClearPool => "esdm_rpcc_rnd_clear_pool",
+            EsdmOperation::GetWriteWakeupThresh => "esdm_rpcc_get_write_wakeup_thresh",
+            EsdmOperation::SetWriteWakeupThresh => "esdm_rpcc_set_write_wakeup_thresh",
+            EsdmOperation::GetMinReseedSecs => "esdm_rpcc_get_min_reseed_secs",
+            EsdmOperation::SetMinReseedSecs => "esdm_rpcc_set_min_reseed_secs",
+            EsdmOperation::GetPoolSize => "esdm_rpcc_get_poolsize",
+            EsdmOperation::GetEntropyLevel => "esdm_rpcc_get_ent_lvl",
+            EsdmOperation::Status => "esdm_rpcc_status",
+            EsdmOperation::JentStatus => "esdm_rpcc_jent_status",
+            EsdmOperation::WaitForNeedEntropy => "esdm_aux_timedwait_for_need_entropy",
+        }
+    }
+
+    /// service this operation is sent to
+    #[must_use]
+    pub fn service(self) -> EsdmService {
+        match self {
+            EsdmOperation::InitPrivService
+            | EsdmOperation::AddEntropy
+            | EsdmOperation::AddToEntropyCount
+            | EsdmOperation::ReseedCrng
+            | EsdmOperation::ClearPool
+            | EsdmOperation::SetWriteWakeupThresh
+            | EsdmOperation::SetMinReseedSecs => EsdmService::Privileged,
+            EsdmOperation::InitAux | EsdmOperation::WaitForNeedEntropy => EsdmService::Aux,
+            _ => EsdmService::Unprivileged,
+        }
+    }
+}
+
+impl fmt::Display for EsdmOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned by all fallible ESDM operations
+///
+/// Converts into [`std::io::Error`] with a matching [`io::ErrorKind`], the original
+/// `EsdmError` stays available via [`io::Error::get_ref`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EsdmError {
+    /// ESDM returned a (negative errno) error code for every attempt
+    Rpc {
+        op: EsdmOperation,
+        code: i32,
+        attempts: u32,
+    },
+
+    /// ESDM returned less random bytes than requested for every attempt
+    ShortRead {
+        op: EsdmOperation,
+        requested: usize,
+        received: usize,
+        attempts: u32,
+    },
+
+    /// ESDM returned a status text, which is not a NUL-terminated UTF-8 string
+    InvalidStatus {
+        op: EsdmOperation,
+        reason: &'static str,
+    },
+
+    /// the buffer passed to ESDM is smaller than the `required` number of bytes
+    BufferTooSmall { op: EsdmOperation, required: usize },
+
+    /// waiting for an ESDM event did not finish before the deadline
+    Timeout { op: EsdmOperation },
+
+    /// ESDM was reachable, but not at the `required` seed level: it did not reach
+    /// it before the deadline of a wait, or an
+    /// [`EsdmRngType::NonBlocking`](crate::EsdmRngType::NonBlocking) request of
+    /// a crypto RNG was refused at once, because ESDM was still unseeded
+    NotSeeded {
+        current: EsdmSeedLevel,
+        required: EsdmSeedLevel,
+    },
+
+    /// the operation was stopped by a [`CancellationToken`](crate::CancellationToken)
+    Cancelled { op: EsdmOperation },
+
+    /// the selected backend cannot perform the operation as requested
+    Unsupported {
+        op: EsdmOperation,
+        reason: &'static str,
+    },
+
+    /// the caller lacks a privilege needed by the privileged ESDM service
+    NotPrivileged {
+        op: EsdmOperation,
+        missing: MissingPrivilege,
+    },
+
+    /// more bits of entropy were to be credited than the data holds (8 per byte)
+    InvalidEntropyCredit { bits: u32, bytes: usize },
+
+    /// a client setting is out of range or could not be parsed, `setting` names
+    /// the builder method, environment variable or file it came from
+    InvalidConfig { setting: String, reason: String },
+
+    /// local system call needed for the operation failed
+    Os {
+        op: EsdmOperation,
+        source: io::Error,
+    },
+}
+
+impl EsdmError {
+    /// operation, which failed
+    #[must_use]
+    pub fn operation(&self) -> EsdmOperation {
+        match self {
+            EsdmError::Rpc { op, .. }
+            | EsdmError::ShortRead { op, .. }
+            | EsdmError::InvalidStatus { op, .. }
+            | EsdmError::BufferTooSmall { op, .. }
+            | EsdmError::Timeout { op }
+            | EsdmError::Cancelled { op }
+            | EsdmError::Unsupported { op, .. }
+            | EsdmError::NotPrivileged { op, .. }
+            | EsdmError::Os { op, .. } => *op,
+            EsdmError::NotSeeded { .. } => EsdmOperation::Status,
+            EsdmError::InvalidConfig { .. } => EsdmOperation::InitUnprivService,
+            EsdmError::InvalidEntropyCredit { .. } => EsdmOperation::AddEntropy,
+        }
+    }
+
+    /// ESDM service the failed operation was sent to
+    #[must_use]
+    pub fn service(&self) -> EsdmService {
+        self.operation().service()
+    }
+
+    /// true, if the failed operation needs the privileged ESDM service
+    #[must_use]
+    pub fn is_privileged(&self) -> bool {
+        self.service() == EsdmService::Privileged
+    }
+
+    /// raw return code of the last ESDM call, if ESDM returned one
+    #[must_use]
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            EsdmError::Rpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// errno describing the failure, if known
+    #[must_use]
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            EsdmError::Rpc { code, .. } => Some(code.saturating_abs()),
+            EsdmError::BufferTooSmall { .. } => Some(libc::EMSGSIZE),
+            EsdmError::Timeout { .. } => Some(libc::ETIMEDOUT),
+            EsdmError::NotSeeded { .. } => Some(libc::EAGAIN),
+            EsdmError::Cancelled { .. } => Some(libc::ECANCELED),
+            EsdmError::Unsupported { .. } => Some(libc::EOPNOTSUPP),
+            EsdmError::NotPrivileged {
+                missing: MissingPrivilege::CapSysAdmin,
+                ..
+            } => Some(libc::EPERM),
+            EsdmError::NotPrivileged { .. } => Some(libc::EACCES),
+            EsdmError::InvalidEntropyCredit { .. } | EsdmError::InvalidConfig { .. } => {
+                Some(libc::EINVAL)
+            }
+            EsdmError::Os { source, .. } => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// number of attempts made before giving up
+    #[must_use]
+    pub fn attempts(&self) -> u32 {
+        match self {
+            EsdmError::Rpc { attempts, .. } | EsdmError::ShortRead { attempts, .. } => *attempts,
+            _ => 1,
+        }
+    }
+
+    /// [`io::ErrorKind`] best describing this error
+    #[must_use]
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            EsdmError::Rpc { .. } | EsdmError::Os { .. } => match self.errno() {
+                Some(libc::EPERM | libc::EACCES) => io::ErrorKind::PermissionDenied,
+                Some(libc::ENOENT) => io::ErrorKind::NotFound,
+                Some(libc::ECONNREFUSED) => io::ErrorKind::ConnectionRefused,
+                Some(libc::ECONNRESET) => io::ErrorKind::ConnectionReset,
+                Some(libc::EPIPE) => io::ErrorKind::BrokenPipe,
+                Some(libc::EINTR) => io::ErrorKind::Interrupted,
+                Some(libc::EAGAIN) => io::ErrorKind::WouldBlock,
+                Some(libc::ETIMEDOUT) => io::ErrorKind::TimedOut,
+                Some(libc::EINVAL) => io::ErrorKind::InvalidInput,
+                _ => io::ErrorKind::Other,
+            },
+            EsdmError::ShortRead { .. } => io::ErrorKind::UnexpectedEof,
+            EsdmError::InvalidStatus { .. } => io::ErrorKind::InvalidData,
+            EsdmError::BufferTooSmall { .. }
+            | EsdmError::InvalidEntropyCredit { .. }
+            | EsdmError::InvalidConfig { .. } => io::ErrorKind::InvalidInput,
+            EsdmError::NotPrivileged { .. } => io::ErrorKind::PermissionDenied,
+            EsdmError::Timeout { .. } => io::ErrorKind::TimedOut,
+            EsdmError::NotSeeded { .. } => io::ErrorKind::WouldBlock,
+            EsdmError::Cancelled { .. } => io::ErrorKind::Interrupted,
+            EsdmError::Unsupported { .. } => io::ErrorKind::Unsupported,
+        }
+    }
+
+    /// true, if esdm-server could not be reached, e.g. it is not running
+    #[must_use]
+    pub fn is_unreachable(&self) -> bool {
+        self.retry_class() == RetryClass::Unavailable
+    }
+
+    /// class of the failure, decides if a [`RetryPolicy`](crate::RetryPolicy) retries it
+    #[must_use]
+    pub fn retry_class(&self) -> RetryClass {
+        match self {
+            EsdmError::Rpc { .. } | EsdmError::Os { .. } => match self.errno() {
+                Some(
+                    libc::EAGAIN | libc::EINTR | libc::EBUSY | libc::ETIMEDOUT | libc::EINPROGRESS,
+                ) => RetryClass::Transient,
+                Some(
+                    libc::ENOENT
+                    | libc::ECONNREFUSED
+                    | libc::ECONNRESET
+                    | libc::ECONNABORTED
+                    | libc::EPIPE
+                    | libc::ENOTCONN
+                    | libc::ESHUTDOWN,
+                ) => RetryClass::Unavailable,
+                _ => RetryClass::Fatal,
+            },
+            EsdmError::ShortRead { .. } => RetryClass::ShortRead,
+            EsdmError::Timeout { .. } | EsdmError::NotSeeded { .. } => RetryClass::Transient,
+            EsdmError::InvalidStatus { .. }
+            | EsdmError::BufferTooSmall { .. }
+            | EsdmError::Cancelled { .. }
+            | EsdmError::Unsupported { .. }
+            | EsdmError::NotPrivileged { .. }
+            | EsdmError::InvalidEntropyCredit { .. }
+            | EsdmError::InvalidConfig { .. } => RetryClass::Fatal,
+        }
+    }
+}
+
+impl fmt::Display for EsdmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EsdmError::Rpc { op, code, attempts } => write!(
+                f,
+                "ESDM {op} failed on {} service after {attempts} attempt(s): {} (code {code})",
+                op.service(),
+                io::Error::from_raw_os_error(code.saturating_abs())
+            ),
+            EsdmError::ShortRead {
+                op,
+                requested,
+                received,
+                attempts,
+            } => write!(
+                f,
+                "ESDM {op} returned {received} of {requested} bytes after {attempts} attempt(s)"
+            ),
+            EsdmError::InvalidStatus { op, reason } => {
+                write!(f, "ESDM {op} returned invalid status text: {reason}")
+            }
+            EsdmError::BufferTooSmall { op, required } => {
+                write!(f, "ESDM {op} needs a buffer of at least {required} bytes")
+            }
+            EsdmError::Timeout { op } => write!(f, "ESDM {op} timed out"),
+            EsdmError::NotSeeded { current, required } => {
+                write!(f, "ESDM is {current}, not {required}")
+            }
+            EsdmError::Cancelled { op } => write!(f, "ESDM {op} was cancelled"),
+            EsdmError::Unsupported { op, reason } => write!(f, "ESDM {op} unsupported: {reason}"),
+            EsdmError::NotPrivileged { op, missing } => write!(f, "ESDM {op} needs {missing}"),
+            EsdmError::InvalidEntropyCredit { bits, bytes } => write!(
+                f,
+                "cannot credit {bits} bits of entropy to {bytes} bytes of data"
+            ),
+            EsdmError::InvalidConfig { setting, reason } => {
+                write!(f, "invalid ESDM client setting {setting}: {reason}")
+            }
+            EsdmError::Os { op, source } => write!(f, "ESDM {op} failed: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for EsdmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EsdmError::Os { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<EsdmError> for io::Error {
+    fn from(err: EsdmError) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_conversion() {
+        let err = EsdmError::Rpc {
+            op: EsdmOperation::AddEntropy,
+            code: -libc::EACCES,
+            attempts: 5,
+        };
+        assert!(err.is_privileged());
+        assert_eq!(err.errno(), Some(libc::EACCES));
+
+        let io_err: io::Error = err.into();
+        assert_eq!(io_err.kind(), io::ErrorKind::PermissionDenied);
+        let inner = io_err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<EsdmError>()
+            .unwrap();
+        assert_eq!(inner.operation(), EsdmOperation::AddEntropy);
+        assert_eq!(inner.attempts(), 5);
+    }
+}
@@ -0,0 +1,309 @@
+//! ESDM with fallback to the kernel's `getrandom`, for hosts without esdm-server
+//!
+//! [`FallbackRng`] prefers ESDM and, depending on its [`FallbackPolicy`],
+//! serves requests from `getrandom` while ESDM is unreachable or failing.
+//! After a fallback, ESDM is tried again once the retry interval passed.
+//! Bytes from `getrandom` carry the guarantees of the kernel RNG, not the ones
+//! of the requested [`EsdmRngType`]: check the reported [`RandomSource`] if
+//! this matters.
+
+use std::time::{Duration, Instant};
+
+use rand_core::{TryCryptoRng, TryRng};
+use zeroize::Zeroizing;
+
+use crate::{EsdmError, EsdmOperation, EsdmRng, EsdmRngType, kernel};
+
+/// default time after a failure, before ESDM is tried again
+pub(crate) const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// when a [`FallbackRng`] (or the `getrandom` backend) uses the kernel's
+/// `getrandom` instead of ESDM
+///
+/// Only errors meaning that ESDM is unavailable or failing lead to a fallback:
+/// [`EsdmError::Rpc`], [`EsdmError::ShortRead`], [`EsdmError::InvalidStatus`],
+/// [`EsdmError::Timeout`] and [`EsdmError::Os`]. Everything else, e.g. a
+/// cancelled request or an invalid setting, is returned unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FallbackPolicy {
+    /// never, all ESDM errors are returned
+    Never,
+
+    /// only while no connection to ESDM can be established
+    OnInitFailure,
+
+    /// whenever ESDM is unavailable or fails to deliver random bytes
+    #[default]
+    OnAnyFailure,
+}
+
+impl FallbackPolicy {
+    /// true, if this policy falls back after `err`
+    pub(crate) fn falls_back_on(self, err: &EsdmError) -> bool {
+        match self {
+            FallbackPolicy::Never => false,
+            FallbackPolicy::OnInitFailure => {
+                is_outage(err) && err.operation() == EsdmOperation::InitUnprivService
+            }
+            FallbackPolicy::OnAnyFailure => is_outage(err),
+        }
+    }
+}
+
+/// true, if `err` means that ESDM is unavailable or failing, not that the
+/// request was refused or cancelled
+fn is_outage(err: &EsdmError) -> bool {
+    matches!(
+        err,
+        EsdmError::Rpc { .. }
+            | EsdmError::ShortRead { .. }
+            | EsdmError::InvalidStatus { .. }
+            | EsdmError::Timeout { .. }
+            | EsdmError::Os { .. }
+    )
+}
+
+/// source which served a request of a [`FallbackRng`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RandomSource {
+    Esdm,
+    Getrandom,
+}
+
+/// random number generator preferring ESDM, falling back to `getrandom`
+#[derive(Debug)]
+pub struct FallbackRng {
+    rng_type: EsdmRngType,
+    policy: FallbackPolicy,
+    retry_interval: Duration,
+    esdm: Option<EsdmRng>,
+    // time of the last fallback, ESDM is skipped until the retry interval passed
+    failed_at: Option<Instant>,
+    last_source: Option<RandomSource>,
+}
+
+impl Default for FallbackRng {
+    /// fully seeded ESDM random bytes, falling back on any failure
+    fn default() -> Self {
+        Self::new(EsdmRngType::FullySeeded, FallbackPolicy::OnAnyFailure)
+            .expect("fallback policy allows init failure")
+    }
+}
+
+impl FallbackRng {
+    /// connects to ESDM, fails only with [`FallbackPolicy::Never`] if ESDM is unreachable
+    pub fn new(rng_type: EsdmRngType, policy: FallbackPolicy) -> Result<Self, EsdmError> {
+        let mut rng = FallbackRng {
+            rng_type,
+            policy,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            esdm: None,
+            failed_at: None,
+            last_source: None,
+        };
+
+        match EsdmRng::try_new(rng_type) {
+            Ok(esdm) => rng.esdm = Some(esdm),
+            Err(err) if !rng.policy.falls_back_on(&err) => return Err(err),
+            Err(_) => rng.failed_at = Some(Instant::now()),
+        }
+
+        Ok(rng)
+    }
+
+    /// sets how long ESDM is skipped after a fallback
+    #[must_use]
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    #[must_use]
+    pub fn rng_type(&self) -> EsdmRngType {
+        self.rng_type
+    }
+
+    #[must_use]
+    pub fn policy(&self) -> FallbackPolicy {
+        self.policy
+    }
+
+    /// source which served the last successful request, `None` before the first one
+    #[must_use]
+    pub fn last_source(&self) -> Option<RandomSource> {
+        self.last_source
+    }
+
+    /// fills `dst` completely with random bytes, returns which source served them
+    pub fn fill_bytes_with_source(&mut self, dst: &mut [u8]) -> Result<RandomSource, EsdmError> {
+        let esdm_due = self
+            .failed_at
+            .is_none_or(|failed_at| failed_at.elapsed() >= self.retry_interval);
+
+        let source = match esdm_due.then(|| self.fill_from_esdm(dst)) {
+            Some(Ok(())) => {
+                self.failed_at = None;
+                RandomSource::Esdm
+            }
+            Some(Err(err)) if !self.policy.falls_back_on(&err) => return Err(err),
+            Some(Err(_)) => {
+                self.failed_at = Some(Instant::now());
+                fill_from_getrandom(self.rng_type.operation(), dst)?;
+                RandomSource::Getrandom
+            }
+            None => {
+                fill_from_getrandom(self.rng_type.operation(), dst)?;
+                RandomSource::Getrandom
+            }
+        };
+
+        self.last_source = Some(source);
+
+        Ok(source)
+    }
+
+    fn fill_from_esdm(&mut self, dst: &mut [u8]) -> Result<(), EsdmError> {
+        let mut esdm = match self.esdm.take() {
+            Some(esdm) => esdm,
+            None => EsdmRng::try_new(self.rng_type)?,
+        };
+        let result = esdm.try_fill_bytes(dst);
+        self.esdm = Some(esdm);
+
+        result
+    }
+}
+
+fn fill_from_getrandom(op: EsdmOperation, dst: &mut [u8]) -> Result<(), EsdmError> {
+    kernel::fill(dst).map_err(|source| EsdmError::Os { op, source })
+}
+
+impl TryRng for FallbackRng {
+    type Error = EsdmError;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        Ok(u32::try_from(self.try_next_u64()? & 0xFF_FF_FF_FF).unwrap())
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        let mut bytes = Zeroizing::new([0u8; 8]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
+
+        Ok(u64::from_ne_bytes(*bytes))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        self.fill_bytes_with_source(dst).map(drop)
+    }
+}
+
+/// both sources are cryptographically secure, unseeded ESDM output of
+/// [`EsdmRngType::NonBlocking`] mode is refused like by [`EsdmRng`]
+impl TryCryptoRng for FallbackRng {}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::CancellationToken;
+    use crate::mock::MockEsdmServer;
+
+    #[test]
+    fn test_fallback_on_any_failure() {
+        let server = MockEsdmServer::start().unwrap();
+        let mut rng = FallbackRng::default();
+        let mut buf = [0u8; 32];
+
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Esdm
+        );
+
+        server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EIO);
+        let calls = server.calls(EsdmOperation::GetRandomBytesFull);
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Getrandom
+        );
+        assert_eq!(rng.last_source(), Some(RandomSource::Getrandom));
+        assert_ne!(buf, [0u8; 32]);
+
+        // ESDM is skipped until the retry interval passed
+        let calls_after_failure = server.calls(EsdmOperation::GetRandomBytesFull);
+        assert!(calls_after_failure > calls);
+        rng.try_next_u64().unwrap();
+        assert_eq!(
+            server.calls(EsdmOperation::GetRandomBytesFull),
+            calls_after_failure
+        );
+
+        server.clear_failures();
+        let mut rng = rng.with_retry_interval(Duration::ZERO);
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Esdm
+        );
+    }
+
+    #[test]
+    fn test_no_fallback_when_cancelled() {
+        let server = MockEsdmServer::start().unwrap();
+        let mut rng = FallbackRng::default();
+        let mut buf = [0u8; 32];
+
+        server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EIO);
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = token
+            .run(|| rng.fill_bytes_with_source(&mut buf))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::Cancelled {
+                op: EsdmOperation::GetRandomBytesFull
+            }
+        ));
+        assert_eq!(buf, [0u8; 32]);
+
+        // a cancelled request does not make the next one skip ESDM
+        server.clear_failures();
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Esdm
+        );
+
+        assert!(
+            !FallbackPolicy::OnAnyFailure.falls_back_on(&EsdmError::InvalidConfig {
+                setting: "max_chunk_size".into(),
+                reason: "must be at least 1".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_fallback_on_init_failure() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_reachable(false);
+
+        let err = FallbackRng::new(EsdmRngType::FullySeeded, FallbackPolicy::Never).unwrap_err();
+        assert_eq!(err.operation(), EsdmOperation::InitUnprivService);
+
+        let mut rng = FallbackRng::new(EsdmRngType::FullySeeded, FallbackPolicy::OnInitFailure)
+            .unwrap()
+            .with_retry_interval(Duration::ZERO);
+        let mut buf = [0u8; 32];
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Getrandom
+        );
+
+        server.set_reachable(true);
+        assert_eq!(
+            rng.fill_bytes_with_source(&mut buf).unwrap(),
+            RandomSource::Esdm
+        );
+
+        // failures of a working connection are not covered by this policy
+        server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EIO);
+        assert!(rng.fill_bytes_with_source(&mut buf).is_err());
+    }
+}
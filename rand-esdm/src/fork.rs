@@ -0,0 +1,29 @@
+//! Fork detection
+//!
+//! A child process inherits the client library state of its parent, including
+//! its sockets to ESDM, and all buffered random bytes. A `pthread_atfork` child
+//! handler counts the forks of this process, handles remember the count they
+//! were set up with and re-initialize or discard their state once it changed.
+//!
+//! Like all code running in a child of a multi-threaded process, this relies on
+//! no other thread of the parent holding a lock of this crate while forking.
+
+use std::sync::Once;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static FORK_GENERATION: AtomicU64 = AtomicU64::new(0);
+static REGISTER_HANDLER: Once = Once::new();
+
+extern "C" fn child_after_fork() {
+    FORK_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// number of forks between the first call of this function and the current process
+pub(crate) fn generation() -> u64 {
+    REGISTER_HANDLER.call_once(|| {
+        let ret = unsafe { libc::pthread_atfork(None, None, Some(child_after_fork)) };
+        assert_eq!(ret, 0, "unable to register fork handler");
+    });
+
+    FORK_GENERATION.load(Ordering::SeqCst)
+}
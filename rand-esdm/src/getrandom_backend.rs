@@ -0,0 +1,172 @@
+//! `getrandom` custom backend routed through ESDM
+//!
+//! [`register_getrandom_backend!`](crate::register_getrandom_backend) defines
+//! the custom backend function of the `getrandom` crate, so that every
+//! `getrandom::fill` in the process (used by `uuid`, `ring`, `rand`'s `SysRng`,
+//! ...) returns fully seeded ESDM random bytes. As required by `getrandom`, the
+//! macro has to be invoked exactly once, in the binary crate, and the backend
+//! has to be enabled with `--cfg getrandom_backend="custom"`.
+//!
+//! While ESDM is unreachable or failing, the [`FallbackPolicy`] set with
+//! [`esdm_set_getrandom_fallback`] decides: by default, requests are served
+//! from the kernel's `getrandom` system call and ESDM is tried again after
+//! 30 s; with [`FallbackPolicy::Never`], the errno of the ESDM failure is
+//! returned as `getrandom::Error::new_custom(errno)`.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use crate::fallback::DEFAULT_RETRY_INTERVAL;
+use crate::{EsdmConnection, EsdmError, EsdmRngType, FallbackPolicy, kernel};
+
+struct BackendState {
+    policy: FallbackPolicy,
+    conn: Option<EsdmConnection>,
+    // time of the last fallback, ESDM is skipped until the retry interval passed
+    failed_at: Option<Instant>,
+}
+
+static STATE: Mutex<BackendState> = Mutex::new(BackendState {
+    policy: FallbackPolicy::OnAnyFailure,
+    conn: None,
+    failed_at: None,
+});
+
+fn state() -> MutexGuard<'static, BackendState> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// sets when the `getrandom` backend falls back to the kernel's `getrandom`
+pub fn esdm_set_getrandom_fallback(policy: FallbackPolicy) {
+    let mut state = state();
+    state.policy = policy;
+    state.failed_at = None;
+}
+
+/// returns when the `getrandom` backend falls back to the kernel's `getrandom`
+#[must_use]
+pub fn esdm_getrandom_fallback() -> FallbackPolicy {
+    state().policy
+}
+
+fn to_getrandom_error(err: &EsdmError) -> getrandom::Error {
+    let errno = err.errno().unwrap_or(libc::EIO);
+    getrandom::Error::new_custom(u16::try_from(errno).unwrap_or(u16::MAX))
+}
+
+fn fill_from_kernel(dst: &mut [u8]) -> Result<(), getrandom::Error> {
+    kernel::fill(dst).map_err(|err| {
+        getrandom::Error::new_custom(
+            err.raw_os_error()
+                .and_then(|errno| u16::try_from(errno).ok())
+                .unwrap_or(u16::MAX),
+        )
+    })
+}
+
+fn fill_from_esdm(dst: &mut [u8]) -> Result<(), EsdmError> {
+    // connect while holding the lock, so that concurrent first calls share one attempt
+    let conn = {
+        let mut state = state();
+        match &state.conn {
+            Some(conn) => conn.clone(),
+            None => state.conn.insert(EsdmConnection::new()?).clone(),
+        }
+    };
+
+    conn.fill_bytes(EsdmRngType::FullySeeded, dst)
+}
+
+/// fills `dst` with fully seeded ESDM random bytes, the implementation behind
+/// [`register_getrandom_backend!`](crate::register_getrandom_backend)
+pub fn esdm_getrandom_fill(dst: &mut [u8]) -> Result<(), getrandom::Error> {
+    let policy = {
+        let state = state();
+        let skip_esdm = state.policy != FallbackPolicy::Never
+            && state
+                .failed_at
+                .is_some_and(|failed_at| failed_at.elapsed() < DEFAULT_RETRY_INTERVAL);
+        if skip_esdm {
+            drop(state);
+            return fill_from_kernel(dst);
+        }
+        state.policy
+    };
+
+    match fill_from_esdm(dst) {
+        Ok(()) => {
+            state().failed_at = None;
+            Ok(())
+        }
+        Err(err) if policy.falls_back_on(&err) => {
+            state().failed_at = Some(Instant::now());
+            fill_from_kernel(dst)
+        }
+        Err(err) => Err(to_getrandom_error(&err)),
+    }
+}
+
+/// Registers ESDM as custom backend of the `getrandom` crate
+///
+/// Invoke once in the binary crate and build with
+/// `RUSTFLAGS='--cfg getrandom_backend="custom"'`:
+///
+/// ```ignore
+/// rand_esdm::register_getrandom_backend!();
+/// ```
+#[macro_export]
+macro_rules! register_getrandom_backend {
+    () => {
+        #[unsafe(no_mangle)]
+        unsafe extern "Rust" fn __getrandom_v03_custom(
+            dest: *mut u8,
+            len: usize,
+        ) -> ::core::result::Result<(), $crate::getrandom::Error> {
+            // getrandom may pass an uninitialized buffer
+            let buf = unsafe {
+                ::core::ptr::write_bytes(dest, 0, len);
+                ::core::slice::from_raw_parts_mut(dest, len)
+            };
+            $crate::esdm_getrandom_fill(buf)
+        }
+    };
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEsdmServer;
+    use crate::{EsdmOperation, EsdmSeedLevel};
+
+    #[test]
+    fn test_getrandom_backend() {
+        let server = MockEsdmServer::start().unwrap();
+        state().conn = None;
+        esdm_set_getrandom_fallback(FallbackPolicy::Never);
+
+        let mut buf = [0u8; 64];
+        esdm_getrandom_fill(&mut buf).unwrap();
+        assert!(buf.iter().any(|b| *b != 0));
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 1);
+
+        server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EPERM);
+        let err = esdm_getrandom_fill(&mut buf).unwrap_err();
+        assert_eq!(err, getrandom::Error::new_custom(1));
+
+        // falls back and skips ESDM for the retry interval
+        esdm_set_getrandom_fallback(FallbackPolicy::OnAnyFailure);
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+        esdm_getrandom_fill(&mut buf).unwrap();
+        let calls = server.calls(EsdmOperation::GetRandomBytesFull);
+        esdm_getrandom_fill(&mut buf).unwrap();
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), calls);
+
+        esdm_set_getrandom_fallback(FallbackPolicy::OnInitFailure);
+        server.clear_failures();
+        server.set_seed_level(EsdmSeedLevel::FullySeeded);
+        esdm_getrandom_fill(&mut buf).unwrap();
+        assert_eq!(esdm_getrandom_fallback(), FallbackPolicy::OnInitFailure);
+        esdm_set_getrandom_fallback(FallbackPolicy::default());
+        state().conn = None;
+    }
+}
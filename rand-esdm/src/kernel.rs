@@ -0,0 +1,38 @@
+//! Random bytes straight from the kernel
+//!
+//! The crate itself never uses the `getrandom` crate, as it may be routed
+//! through ESDM (see the `getrandom_backend` feature).
+
+use std::io;
+
+/// fills `buf` with random bytes of the kernel's `getrandom` system call
+pub(crate) fn fill(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let ret = unsafe {
+            libc::getrandom(
+                buf[filled..].as_mut_ptr().cast::<libc::c_void>(),
+                buf.len() - filled,
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += ret.unsigned_abs();
+    }
+
+    Ok(())
+}
+
+/// random `u32` of the kernel's `getrandom` system call
+pub(crate) fn u32() -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    fill(&mut bytes)?;
+
+    Ok(u32::from_ne_bytes(bytes))
+}
@@ -1,26 +1,112 @@
 use libc::ETIMEDOUT;
-use rand_core::TryRng;
-use regex::Regex;
-use std::ffi::{CString, c_char};
+use rand_core::{SeedableRng, TryCryptoRng, TryRng};
+use std::cmp::Ordering;
+use std::ffi::CString;
 use std::mem::MaybeUninit;
-
-use std::io::Error;
-use std::sync::Mutex;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
 use std::time::Duration;
-
-use esdm_sys::esdm::{self, esdm_rpcc_set_max_online_nodes};
-use esdm_sys::esdm_aux;
+use zeroize::{Zeroize, Zeroizing};
+
+mod admin;
+#[cfg(feature = "async")]
+mod async_rng;
+mod backend;
+mod buffered;
+mod cancel;
+mod client;
+mod config;
+mod connection;
+mod error;
+mod fallback;
+mod fork;
+#[cfg(feature = "getrandom_backend")]
+mod getrandom_backend;
+mod kernel;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod retry;
+mod secrets;
+mod seed;
+mod status;
+mod thread_rng;
+mod watcher;
+
+pub use admin::{EntropyCredit, EsdmAdmin};
+#[cfg(feature = "async")]
+pub use async_rng::{AsyncEsdmRng, NeedEntropyStream};
+pub use buffered::BufferedEsdmRng;
+pub use cancel::CancellationToken;
+#[allow(deprecated)]
+pub use client::esdm_set_max_online_nodes;
+pub use client::{EsdmClient, EsdmClientBuilder, esdm_max_online_nodes};
+#[cfg(feature = "native")]
+pub use config::EsdmClientConfig;
+pub use config::SOCKET_DIR_ENV;
+pub use connection::{EsdmConnection, EsdmPrivConnection};
+pub use error::{EsdmError, EsdmOperation, EsdmService, MissingPrivilege};
+pub use fallback::{FallbackPolicy, FallbackRng, RandomSource};
+#[cfg(feature = "getrandom_backend")]
+pub use getrandom_backend::{
+    esdm_getrandom_fallback, esdm_getrandom_fill, esdm_set_getrandom_fallback,
+};
+// used by `register_getrandom_backend!`
+#[cfg(feature = "getrandom_backend")]
+#[doc(hidden)]
+pub use getrandom;
+pub use retry::{RetryClass, RetryPolicy, esdm_retry_policy, esdm_set_retry_policy};
+pub use secrets::{SecretBytes, SecretKey};
+pub use seed::{SeedFlags, SeedInfo, SeedSource};
+pub use status::{EsdmNodeStatus, EsdmSeedLevel, EsdmStatus};
+pub use thread_rng::{EsdmThreadRng, esdm_rng};
+pub use watcher::{EsdmEvent, EsdmWatcher, WatchOptions};
 
 /*
  * private ESDM RPC client function definitions
  */
 
-// how often to retry RPC calls before returning an error
-const ESDM_RETRY_COUNT: u32 = 5;
+/// calls an ESDM function returning 0 on success, retried according to `policy`
+fn esdm_retry(
+    op: EsdmOperation,
+    policy: &RetryPolicy,
+    mut call: impl FnMut() -> i32,
+) -> Result<(), EsdmError> {
+    policy.run(|attempts| match call() {
+        0 => Ok(()),
+        code => Err(EsdmError::Rpc { op, code, attempts }),
+    })
+}
 
-static LIB_MUTEX_UNPRIV: Mutex<u32> = Mutex::new(0u32);
-static LIB_MUTEX_PRIV: Mutex<u32> = Mutex::new(0u32);
+/// fetches a NUL-terminated status text from ESDM
+fn esdm_status_text(
+    op: EsdmOperation,
+    policy: &RetryPolicy,
+    call: impl Fn(&mut [u8]) -> i32,
+) -> Result<String, EsdmError> {
+    let mut status_bytes = vec![0u8; 8192];
+    esdm_retry(op, policy, || call(&mut status_bytes))?;
+
+    let Some(nul_pos) = status_bytes.iter().position(|b| *b == 0) else {
+        return Err(EsdmError::InvalidStatus {
+            op,
+            reason: "missing NUL terminator",
+        });
+    };
+    status_bytes.truncate(nul_pos + 1);
+
+    CString::from_vec_with_nul(status_bytes)
+        .map_err(|_| EsdmError::InvalidStatus {
+            op,
+            reason: "interior NUL byte",
+        })?
+        .into_string()
+        .map_err(|_| EsdmError::InvalidStatus {
+            op,
+            reason: "not valid UTF-8",
+        })
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EsdmRngType {
     /// ESDM RNG implementation, which uses fresh entropy for every random output produced
     PredictionResistant,
@@ -28,105 +114,196 @@ pub enum EsdmRngType {
     /// ESDM RNG implementation, which only produces random numbers when fully seeded
     /// otherwise it times out and returns an error after a few internal tries
     FullySeeded,
+
+    /// ESDM RNG implementation, which produces random numbers once minimally seeded
+    MinimallySeeded,
+
+    /// ESDM RNG implementation, which never blocks and produces random numbers
+    /// even before being seeded (like `/dev/urandom` during early boot), use
+    /// [`EsdmConnection::fill_bytes_with_seed_level`] to learn about the seeding
+    ///
+    /// Output generated before ESDM is seeded is not cryptographically secure.
+    /// [`EsdmRng`] and the other [`TryCryptoRng`] types therefore fail with
+    /// [`EsdmError::NotSeeded`] in this mode until ESDM is seeded, only
+    /// [`EsdmConnection::fill_bytes`] and the `fill_bytes_with_seed_level`
+    /// methods hand out unseeded output.
+    NonBlocking,
 }
 
+/// random number generator backed by ESDM, keeps its connection alive
+#[derive(Debug, Clone)]
 pub struct EsdmRng {
+    conn: EsdmConnection,
     rng_type: EsdmRngType,
 }
 
-/// Returns if the client connection to ESDM was initialized succesfully
-/// Only needed to call once globally before first usage of ESDM
-#[must_use]
-pub fn esdm_rng_init() -> bool {
-    let mut guard = LIB_MUTEX_UNPRIV.lock().unwrap();
+impl EsdmRng {
+    /// connects to ESDM, panics if the connection cannot be established
+    #[must_use]
+    pub fn new(rng_type: EsdmRngType) -> Self {
+        Self::try_new(rng_type).expect("unable to connect to ESDM")
+    }
 
-    let ret = if *guard == 0 {
-        unsafe { esdm::esdm_rpcc_init_unpriv_service(None) == 0 }
-    } else {
-        true
-    };
+    pub fn try_new(rng_type: EsdmRngType) -> Result<Self, EsdmError> {
+        Ok(Self::with_connection(EsdmConnection::new()?, rng_type))
+    }
 
-    if ret {
-        *guard += 1;
+    pub(crate) fn with_connection(conn: EsdmConnection, rng_type: EsdmRngType) -> Self {
+        EsdmRng { conn, rng_type }
     }
 
-    ret
-}
+    #[must_use]
+    pub fn rng_type(&self) -> EsdmRngType {
+        self.rng_type
+    }
 
-/// initializes the client connection to ESDM, asserts if something goes wrong
-/// Only needed to call once globally before first usage of ESDM
-pub fn esdm_rng_init_checked() {
-    let success = esdm_rng_init();
-    assert!(success);
-}
+    /// sets the retry policy of this generator
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.conn = self.conn.with_retry_policy(policy);
+        self
+    }
 
-/// Call in order to free ressources needed for ESDM client connection
-pub fn esdm_rng_fini() {
-    let mut guard = LIB_MUTEX_UNPRIV.lock().unwrap();
-    assert_ne!(*guard, 0);
+    /// sets the maximum number of bytes requested from ESDM at once
+    #[must_use]
+    pub fn with_max_chunk_size(mut self, max_chunk_size: NonZeroUsize) -> Self {
+        self.conn = self.conn.with_max_chunk_size(max_chunk_size);
+        self
+    }
 
-    if *guard == 1 {
-        unsafe { esdm::esdm_rpcc_fini_unpriv_service() };
+    /// requests stop once `token` is cancelled
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.conn = self.conn.with_cancellation(token);
+        self
     }
 
-    *guard -= 1;
-}
+    /// the connection used by this generator
+    #[must_use]
+    pub fn connection(&self) -> &EsdmConnection {
+        &self.conn
+    }
 
-pub fn esdm_set_max_online_nodes(nodes: u32) {
-    unsafe {
-        esdm_rpcc_set_max_online_nodes(nodes);
+    /// creates a generator `R` seeded with random bytes of this generator's type
+    pub fn seed_rng<R: SeedableRng>(&mut self) -> Result<R, EsdmError> {
+        seed::seed_rng(&self.conn, self.rng_type)
     }
-}
 
-/// initializes the client connection to ESDM, asserts if something goes wrong
-/// Only needed to call once globally before first usage of ESDM (privileged mode)
-#[must_use]
-pub fn esdm_rng_init_priv() -> bool {
-    let mut guard = LIB_MUTEX_PRIV.lock().unwrap();
+    /// fills `dst` like `try_fill_bytes`, returns the seed level of ESDM
+    /// before the bytes were generated
+    pub fn fill_bytes_with_seed_level(
+        &mut self,
+        dst: &mut [u8],
+    ) -> Result<EsdmSeedLevel, EsdmError> {
+        self.conn.fill_bytes_with_seed_level(self.rng_type, dst)
+    }
 
-    let ret = if *guard == 0 {
-        unsafe { esdm::esdm_rpcc_init_priv_service(None) == 0 }
-    } else {
-        true
-    };
+    /// returns a random key of `N` bytes, which is zeroed on drop
+    pub fn generate_key<const N: usize>(&mut self) -> Result<SecretKey<N>, EsdmError> {
+        let mut key = SecretKey::zeroed();
+        self.try_fill_bytes(key.expose_secret_mut())?;
 
-    if ret {
-        *guard += 1;
+        Ok(key)
     }
 
-    ret
-}
+    /// like [`generate_key`](Self::generate_key), locks the key into RAM before
+    /// filling it, fails with [`EsdmError::Os`] if it cannot be locked
+    pub fn generate_locked_key<const N: usize>(&mut self) -> Result<SecretKey<N>, EsdmError> {
+        let mut key = SecretKey::zeroed_locked().map_err(|source| self.lock_error(source))?;
+        self.try_fill_bytes(key.expose_secret_mut())?;
 
-/// initializes the client connection to ESDM, asserts if something goes wrong
-/// Only needed to call once globally before first usage of ESDM (privileged mode)
-pub fn esdm_rng_init_priv_checked() {
-    let success = esdm_rng_init_priv();
-    assert!(success);
-}
+        Ok(key)
+    }
 
-/// Call in order to free ressources needed for ESDM client connection (privileged mode)
-pub fn esdm_rng_fini_priv() {
-    let mut guard = LIB_MUTEX_PRIV.lock().unwrap();
-    assert_ne!(*guard, 0);
+    /// returns `len` random bytes, which are zeroed on drop
+    pub fn generate_secret(&mut self, len: usize) -> Result<SecretBytes, EsdmError> {
+        let mut secret = SecretBytes::zeroed(len);
+        self.try_fill_bytes(secret.expose_secret_mut())?;
 
-    if *guard == 1 {
-        unsafe { esdm::esdm_rpcc_fini_priv_service() };
+        Ok(secret)
     }
 
-    *guard -= 1;
-}
+    /// like [`generate_secret`](Self::generate_secret), locks the bytes into
+    /// RAM before filling them, fails with [`EsdmError::Os`] if they cannot be
+    /// locked
+    pub fn generate_locked_secret(&mut self, len: usize) -> Result<SecretBytes, EsdmError> {
+        let mut secret =
+            SecretBytes::zeroed_locked(len).map_err(|source| self.lock_error(source))?;
+        self.try_fill_bytes(secret.expose_secret_mut())?;
 
-impl EsdmRng {
-    #[must_use]
-    pub fn new(rng_type: EsdmRngType) -> Self {
-        esdm_rng_init_checked();
-        EsdmRng { rng_type }
+        Ok(secret)
+    }
+
+    fn lock_error(&self, source: std::io::Error) -> EsdmError {
+        EsdmError::Os {
+            op: self.rng_type.operation(),
+            source,
+        }
     }
 }
 
-impl Drop for EsdmRng {
-    fn drop(&mut self) {
-        esdm_rng_fini();
+impl EsdmRngType {
+    /// operation used to fetch random bytes of this type
+    pub(crate) fn operation(self) -> EsdmOperation {
+        match self {
+            EsdmRngType::FullySeeded => EsdmOperation::GetRandomBytesFull,
+            EsdmRngType::PredictionResistant => EsdmOperation::GetRandomBytesPr,
+            EsdmRngType::MinimallySeeded => EsdmOperation::GetRandomBytesMin,
+            EsdmRngType::NonBlocking => EsdmOperation::GetRandomBytes,
+        }
+    }
+
+    /// fills `dst` completely with random bytes of this type
+    ///
+    /// Requests up to `max_chunk` bytes per RPC and continues after short
+    /// reads, every RPC is retried according to `policy`. On failure `dst` is
+    /// zeroed, so no partial random output is left behind.
+    pub(crate) fn fill_bytes(
+        self,
+        policy: &RetryPolicy,
+        max_chunk: usize,
+        dst: &mut [u8],
+    ) -> Result<(), EsdmError> {
+        let op = self.operation();
+
+        let mut filled = 0;
+        while filled < dst.len() {
+            let chunk_end = dst.len().min(filled.saturating_add(max_chunk));
+            let received = policy.run(|attempts| {
+                let chunk = &mut dst[filled..chunk_end];
+                let ret_size = match self {
+                    EsdmRngType::FullySeeded => backend::get_random_bytes_full(chunk),
+                    EsdmRngType::PredictionResistant => backend::get_random_bytes_pr(chunk),
+                    EsdmRngType::MinimallySeeded => backend::get_random_bytes_min(chunk),
+                    EsdmRngType::NonBlocking => backend::get_random_bytes(chunk),
+                };
+
+                match ret_size.cmp(&0) {
+                    Ordering::Greater => Ok(ret_size.unsigned_abs().min(chunk.len())),
+                    Ordering::Less => Err(EsdmError::Rpc {
+                        op,
+                        code: i32::try_from(ret_size).unwrap_or(i32::MIN),
+                        attempts,
+                    }),
+                    Ordering::Equal => Err(EsdmError::ShortRead {
+                        op,
+                        requested: dst.len(),
+                        received: filled,
+                        attempts,
+                    }),
+                }
+            });
+
+            match received {
+                Ok(received) => filled += received,
+                Err(err) => {
+                    dst.zeroize();
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -134,289 +311,191 @@ impl Drop for EsdmRng {
  * rand_core trait implementations
  */
 impl TryRng for EsdmRng {
-    type Error = std::io::Error;
+    type Error = EsdmError;
 
     fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
         Ok(u32::try_from(self.try_next_u64()? & 0xFF_FF_FF_FF).unwrap())
     }
 
     fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
-        let mut bytes: [u8; 8] = [0; 8];
-        self.try_fill_bytes(&mut bytes)?;
+        let mut bytes = Zeroizing::new([0u8; 8]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
 
-        Ok(u64::from_ne_bytes(bytes))
+        Ok(u64::from_ne_bytes(*bytes))
     }
 
+    /// fails with [`EsdmError::NotSeeded`] in [`EsdmRngType::NonBlocking`] mode
+    /// before ESDM is seeded
     fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
-        for _ in 0..ESDM_RETRY_COUNT {
-            let ret_size = match self.rng_type {
-                EsdmRngType::FullySeeded => unsafe {
-                    esdm::esdm_rpcc_get_random_bytes_full(dst.as_mut_ptr(), dst.len())
-                },
-                EsdmRngType::PredictionResistant => unsafe {
-                    esdm::esdm_rpcc_get_random_bytes_pr(dst.as_mut_ptr(), dst.len())
-                },
-            };
-            if ret_size == isize::try_from(dst.len()).unwrap() {
-                return Ok(());
-            }
-        }
-
-        Err(Error::other("Unable to fetch random bytes from ESDM"))
+        self.conn.fill_seeded_bytes(self.rng_type, dst)
     }
 }
 
-/*
- * ESDM specific or privileged functions
- */
-/// returns true, if write of data was a success
-pub fn esdm_write_data(data: &[u8]) -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe { esdm::esdm_rpcc_write_data(data.as_ptr(), data.len()) };
-        if ret == 0 {
-            return Ok(());
-        }
-    }
+/// ESDM only hands out bytes of its seeded DRNG, [`EsdmRngType::NonBlocking`]
+/// requests fail until ESDM is seeded
+impl TryCryptoRng for EsdmRng {}
 
-    Err(Error::other("ESDM error write"))
-}
+/// how long one wait of [`EsdmNotification::wait_for_entropy_needed`] lasts
+/// before it is re-armed
+const NEED_ENTROPY_WAIT_SLICE: Duration = Duration::from_mins(1);
 
-pub fn esdm_crng_reseed() -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe { esdm::esdm_rpcc_rnd_reseed_crng() };
-        if ret == 0 {
-            return Ok(());
-        }
-    }
+/// how often a need-entropy wait checks for cancellation
+const NEED_ENTROPY_CANCEL_CHECK: Duration = Duration::from_millis(250);
 
-    Err(Error::other("ESDM error reseed crng"))
-}
+const NSEC_PER_SEC: libc::c_long = 1_000_000_000;
 
-pub fn esdm_get_entropy_count() -> Result<u32, Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ent_cnt: u32 = 0;
-        let ret =
-            unsafe { esdm::esdm_rpcc_rnd_get_ent_cnt(std::ptr::addr_of!(ent_cnt).cast_mut()) };
-        if ret == 0 {
-            return Ok(ent_cnt);
-        }
-    }
-    Err(Error::other("ESDM error get entropy"))
-}
+/// `ts` advanced by `dur`, saturating at the largest representable time
+fn timespec_add(ts: libc::timespec, dur: Duration) -> libc::timespec {
+    // both summands are below one second, so this cannot overflow
+    let nsec = ts.tv_nsec + libc::c_long::from(dur.subsec_nanos());
+    let sec = ts
+        .tv_sec
+        .saturating_add(libc::time_t::try_from(dur.as_secs()).unwrap_or(libc::time_t::MAX))
+        .saturating_add(libc::time_t::from(nsec >= NSEC_PER_SEC));
 
-pub fn esdm_add_entropy(entropy_bytes: &[u8], entropy_count: u32) -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe {
-            esdm::esdm_rpcc_rnd_add_entropy(
-                entropy_bytes.as_ptr(),
-                entropy_bytes.len(),
-                entropy_count,
-            )
-        };
-        if ret == 0 {
-            return Ok(());
-        }
+    libc::timespec {
+        tv_sec: sec,
+        tv_nsec: nsec % NSEC_PER_SEC,
     }
-
-    Err(Error::other("ESDM error add entropy"))
 }
 
-pub fn esdm_add_to_entropy_count(entropy_increment: u32) -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe { esdm::esdm_rpcc_rnd_add_to_ent_cnt(entropy_increment) };
-        if ret == 0 {
-            return Ok(());
-        }
+/// absolute `CLOCK_MONOTONIC` time `dur` from now
+fn monotonic_deadline(dur: Duration) -> Result<libc::timespec, EsdmError> {
+    let mut now: libc::timespec = unsafe { MaybeUninit::zeroed().assume_init() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut now) } != 0 {
+        return Err(EsdmError::Os {
+            op: EsdmOperation::WaitForNeedEntropy,
+            source: std::io::Error::last_os_error(),
+        });
     }
-    Err(Error::other("ESDM error add entropy count"))
-}
 
-pub fn esdm_reseed_crng() -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe { esdm::esdm_rpcc_rnd_reseed_crng() };
-        if ret == 0 {
-            return Ok(());
-        }
-    }
-    Err(Error::other("ESDM error reseed crng"))
+    Ok(timespec_add(now, dur))
 }
 
-pub fn esdm_clear_pool() -> Result<(), Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe { esdm::esdm_rpcc_rnd_clear_pool() };
-        if ret == 0 {
-            return Ok(());
-        }
-    }
-    Err(Error::other("ESDM error clear pool"))
+/// Notification about ESDM needing entropy
+///
+/// Waits for the aux client signal ESDM sends once its entropy count drops
+/// below the write wakeup threshold, e.g. to feed it from a hardware source.
+/// The aux client is initialized as long as any notification is alive, any
+/// number of them can be used from any number of threads at once. Each signal
+/// of ESDM wakes at least one of the waiting threads.
+#[derive(Debug, Clone)]
+pub struct EsdmNotification {
+    conn: EsdmConnection,
+    aux: Arc<connection::ServiceRef>,
 }
 
-pub fn esdm_write_wakeup_thresh() -> Result<u32, Error> {
-    let write_wakeup_thresh: u32 = 0;
-    for _ in 0..ESDM_RETRY_COUNT {
-        let ret = unsafe {
-            esdm::esdm_rpcc_get_write_wakeup_thresh(
-                std::ptr::addr_of!(write_wakeup_thresh).cast_mut(),
-            )
-        };
-        if ret == 0 {
-            return Ok(write_wakeup_thresh);
-        }
+impl Default for EsdmNotification {
+    fn default() -> Self {
+        Self::new()
     }
-
-    Err(Error::other("ESDM error write wakeup thresh"))
 }
 
-pub fn esdm_jent_status_str() -> Result<String, Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let mut status_bytes = vec![0; 8192];
-
-        let ret = unsafe {
-            esdm::esdm_rpcc_jent_status(
-                status_bytes.as_mut_ptr().cast::<c_char>(),
-                status_bytes.len(),
-            )
-        };
-        if ret == 0 {
-            for i in 0..status_bytes.len() {
-                if status_bytes[i] == 0u8 {
-                    status_bytes.resize(i + 1, 0);
-                    break;
-                }
-            }
-            let str = CString::from_vec_with_nul(status_bytes).unwrap();
-            return Ok(str.into_string().unwrap());
-        }
+impl EsdmNotification {
+    /// # Panics
+    /// if the aux client or the connection to ESDM cannot be set up, see [`EsdmNotification::try_new`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::try_new().expect("unable to initialize ESDM aux client")
     }
-    Err(Error::other("ESDM error jent status"))
-}
 
-pub fn esdm_status_str() -> Result<String, Error> {
-    for _ in 0..ESDM_RETRY_COUNT {
-        let mut status_bytes = vec![0; 8192];
-        let ret = unsafe {
-            esdm::esdm_rpcc_status(
-                status_bytes.as_mut_ptr().cast::<c_char>(),
-                status_bytes.len(),
-            )
-        };
-        if ret == 0 {
-            for i in 0..status_bytes.len() {
-                if status_bytes[i] == 0u8 {
-                    status_bytes.resize(i + 1, 0);
-                    break;
-                }
-            }
-            let str = CString::from_vec_with_nul(status_bytes).unwrap();
-            return Ok(str.into_string().unwrap());
-        }
+    /// initializes the aux client (retried according to the global retry policy)
+    pub fn try_new() -> Result<Self, EsdmError> {
+        Self::with_connection(EsdmConnection::new()?)
     }
-    Err(Error::other("ESDM error status"))
-}
 
-#[must_use]
-pub fn esdm_is_fully_seeded() -> Option<bool> {
-    if !esdm_rng_init() {
-        return None;
-    }
+    /// initializes the aux client, the entropy count is queried through `conn`
+    pub(crate) fn with_connection(conn: EsdmConnection) -> Result<Self, EsdmError> {
+        let aux = connection::ServiceRef::acquire(EsdmService::Aux)?;
 
-    if let Ok(status) = esdm_status_str() {
-        if status.contains("ESDM fully seeded: true") {
-            esdm_rng_fini();
-            return Some(true);
-        }
-        if status.contains("ESDM fully seeded: false") {
-            esdm_rng_fini();
-            return Some(false);
-        }
+        Ok(EsdmNotification {
+            conn,
+            aux: Arc::new(aux),
+        })
     }
 
-    esdm_rng_fini();
-
-    None
-}
-
-#[must_use]
-pub fn esdm_get_entropy_level() -> Option<u32> {
-    if !esdm_rng_init() {
-        return None;
+    /// returns a handle, whose waits stop once `token` is cancelled
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.conn = self.conn.with_cancellation(token);
+        self
     }
 
-    if let Ok(status) = esdm_status_str() {
-        let entropy_level_regex = Regex::new(r"^ESDM entropy level: (?<level>\d+)$").unwrap();
-        for line in status.split('\n') {
-            if let Some(caps) = entropy_level_regex.captures(line) {
-                let level_str = caps.get(1).unwrap().as_str();
-                let level = level_str.parse::<u32>().unwrap();
-                esdm_rng_fini();
-                return Some(level);
+    /// waits until ESDM needs entropy, returns its entropy count in bits
+    pub fn wait_for_entropy_needed(&self) -> Result<u32, EsdmError> {
+        loop {
+            match self.wait_for_entropy_needed_timeout(NEED_ENTROPY_WAIT_SLICE) {
+                Err(EsdmError::Timeout { .. }) => {}
+                res => return res,
             }
         }
     }
 
-    esdm_rng_fini();
+    /// waits at most `dur` until ESDM needs entropy, returns its entropy count
+    /// in bits or [`EsdmError::Timeout`]
+    pub fn wait_for_entropy_needed_timeout(&self, dur: Duration) -> Result<u32, EsdmError> {
+        self.aux.ensure_current()?;
+        let _scope = self.conn.enter()?;
 
-    None
-}
+        let deadline = monotonic_deadline(dur)?;
+        loop {
+            if cancel::is_cancelled() {
+                return Err(EsdmError::Cancelled {
+                    op: EsdmOperation::WaitForNeedEntropy,
+                });
+            }
 
-pub struct EsdmNotification {}
+            // the aux client knows nothing about cancellation, wake up regularly
+            let check = monotonic_deadline(NEED_ENTROPY_CANCEL_CHECK)?;
+            let last = (check.tv_sec, check.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec);
+            let wait_until = if last { deadline } else { check };
+
+            match backend::aux_timedwait_for_need_entropy(&wait_until) {
+                0 => break,
+                ETIMEDOUT if last => {
+                    return Err(EsdmError::Timeout {
+                        op: EsdmOperation::WaitForNeedEntropy,
+                    });
+                }
+                ETIMEDOUT => {}
+                // interrupted by a signal, wait for the rest of the time
+                code if code == -libc::EINTR => {}
+                code => {
+                    return Err(EsdmError::Rpc {
+                        op: EsdmOperation::WaitForNeedEntropy,
+                        code,
+                        attempts: 1,
+                    });
+                }
+            }
+        }
 
-impl Default for EsdmNotification {
-    fn default() -> Self {
-        Self::new()
+        self.conn.entropy_count()
     }
 }
 
-impl Drop for EsdmNotification {
-    fn drop(&mut self) {
-        unsafe { esdm_aux::esdm_aux_fini_wait_for_need_entropy() };
-    }
-}
+// these tests assume a running esdm-server on the system, unless the `mock` feature is enabled!
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl EsdmNotification {
-    #[must_use]
-    pub fn new() -> Self {
-        let ret = unsafe { esdm_aux::esdm_aux_init_wait_for_need_entropy() };
-        assert!(ret == 0, "unable to initialize ESDM aux library");
-        EsdmNotification {}
+    #[cfg(feature = "mock")]
+    fn test_server() -> mock::MockEsdmServer {
+        mock::MockEsdmServer::start().unwrap()
     }
 
-    pub fn wait_for_entropy_needed_timeout(&mut self, dur: Duration) -> Result<u32, Error> {
-        let mut ts: libc::timespec = unsafe { MaybeUninit::zeroed().assume_init() };
-        if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut ts) } != 0 {
-            return Err(Error::other("get entropy clock failed"));
-        }
-
-        let mut ts_esdm = esdm_aux::timespec {
-            tv_sec: ts.tv_sec,
-            tv_nsec: ts.tv_nsec,
-        };
-
-        ts_esdm.tv_sec += i64::try_from(dur.as_secs()).unwrap();
-        ts_esdm.tv_nsec += i64::from(dur.subsec_nanos());
-        ts_esdm.tv_sec += ts.tv_nsec / 1_000_000_000;
-        ts_esdm.tv_nsec %= 1_000_000_000;
-        let ret = unsafe {
-            esdm_aux::esdm_aux_timedwait_for_need_entropy(std::ptr::addr_of_mut!(ts_esdm))
-        };
-        if ret == ETIMEDOUT {
-            return Err(Error::other("get entropy timed out"));
-        }
+    /// stands in for the mock server, the tests talk to the system's esdm-server
+    #[cfg(not(feature = "mock"))]
+    struct SystemServer;
 
-        match esdm_get_entropy_count() {
-            Ok(cnt) => Ok(cnt),
-            _ => Err(Error::other("ESDM error get entropy count")),
-        }
+    #[cfg(not(feature = "mock"))]
+    fn test_server() -> SystemServer {
+        SystemServer
     }
-}
-
-// these tests assume a running esdm-server on the system!
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
     fn test_prediction_resistant_mode() {
+        let _server = test_server();
         let mut rng = EsdmRng::new(EsdmRngType::PredictionResistant);
 
         for _ in 1..1000 {
@@ -427,18 +506,98 @@ mod tests {
 
     #[test]
     fn test_write_wakeup_thresh() {
-        esdm_rng_init_checked();
+        let _server = test_server();
+        let conn = EsdmConnection::new().unwrap();
 
-        let write_wakup_thresh = esdm_write_wakeup_thresh().unwrap();
+        let write_wakup_thresh = conn.write_wakeup_thresh().unwrap();
         assert_ne!(write_wakup_thresh, 0);
 
         println!("write wakeup thresh: {write_wakup_thresh}");
+    }
+
+    #[test]
+    fn test_timespec_add() {
+        let ts = libc::timespec {
+            tv_sec: 10,
+            tv_nsec: 900_000_000,
+        };
+
+        let deadline = timespec_add(ts, Duration::new(2, 200_000_000));
+        assert_eq!((deadline.tv_sec, deadline.tv_nsec), (13, 100_000_000));
+
+        let deadline = timespec_add(ts, Duration::from_millis(100));
+        assert_eq!((deadline.tv_sec, deadline.tv_nsec), (11, 0));
+
+        let deadline = timespec_add(ts, Duration::MAX);
+        assert_eq!(deadline.tv_sec, libc::time_t::MAX);
+        assert!(deadline.tv_nsec < NSEC_PER_SEC);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_notification() {
+        let server = test_server();
+        server.set_write_wakeup_thresh(128);
+        server.set_entropy_count(256);
+
+        let notification = EsdmNotification::try_new().unwrap();
+        let err = notification
+            .wait_for_entropy_needed_timeout(Duration::from_millis(50))
+            .unwrap_err();
+        assert!(matches!(err, EsdmError::Timeout { .. }));
+
+        // waiters on several threads and instances all see the event, dropping
+        // one instance keeps the aux client of the others alive
+        let waiters: Vec<_> = (0..4)
+            .map(|i| {
+                let notification = if i % 2 == 0 {
+                    notification.clone()
+                } else {
+                    EsdmNotification::try_new().unwrap()
+                };
+                std::thread::spawn(move || notification.wait_for_entropy_needed())
+            })
+            .collect();
+        drop(notification);
+        server.set_entropy_count(64);
+
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap().unwrap(), 64);
+        }
+    }
 
-        esdm_rng_fini();
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_notification_once_per_drop() {
+        let server = test_server();
+        server.set_write_wakeup_thresh(128);
+        server.set_entropy_count(64);
+
+        let notification = EsdmNotification::try_new().unwrap();
+        assert_eq!(notification.wait_for_entropy_needed().unwrap(), 64);
+
+        // the pool stays low, that is no new event
+        let err = notification
+            .wait_for_entropy_needed_timeout(Duration::from_millis(700))
+            .unwrap_err();
+        assert!(matches!(err, EsdmError::Timeout { .. }));
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                server.set_entropy_count(256);
+                std::thread::sleep(Duration::from_secs(1));
+                server.set_entropy_count(32);
+            });
+            let entropy_count = notification
+                .wait_for_entropy_needed_timeout(Duration::from_secs(5))
+                .unwrap();
+            assert_eq!(entropy_count, 32);
+        });
     }
 
     #[test]
     fn test_reuse() {
+        let _server = test_server();
         for _ in 0..1000 {
             let rng = &mut EsdmRng::new(EsdmRngType::FullySeeded);
             let _ = rng.try_next_u64().unwrap();
@@ -447,6 +606,7 @@ mod tests {
 
     #[test]
     fn test_multithreading() {
+        let _server = test_server();
         let mut threads = vec![];
         let rng = &mut EsdmRng::new(EsdmRngType::FullySeeded);
         let _ = rng.try_next_u64().unwrap();
@@ -467,8 +627,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_large_request() {
+        let _server = test_server();
+        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+
+        let mut buf = vec![0u8; 1 << 20];
+        rng.try_fill_bytes(&mut buf).unwrap();
+        assert!(!buf.ends_with(&[0u8; 64]));
+    }
+
     #[test]
     fn test_fully_seeded_mode() {
+        let _server = test_server();
         let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
 
         for _ in 1..1000 {
@@ -477,38 +648,237 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_minimally_seeded_and_non_blocking_modes() {
+        let _server = test_server();
+
+        let mut rng = EsdmRng::new(EsdmRngType::MinimallySeeded);
+        let _ = rng.try_next_u64().unwrap();
+
+        let mut rng = EsdmRng::new(EsdmRngType::NonBlocking);
+        let mut buf = [0u8; 32];
+        let seed_level = rng.fill_bytes_with_seed_level(&mut buf).unwrap();
+        println!("non-blocking bytes generated {seed_level:?}");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_non_blocking_crypto_rng_needs_seeding() {
+        let server = test_server();
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+
+        let mut rng = EsdmRng::new(EsdmRngType::NonBlocking);
+        let err = rng.try_next_u64().unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::NotSeeded {
+                current: EsdmSeedLevel::Unseeded,
+                ..
+            }
+        ));
+        assert_eq!(err.errno(), Some(libc::EAGAIN));
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytes), 0);
+
+        // the seed level reporting API still serves unseeded output
+        let mut buf = [0u8; 32];
+        let seed_level = rng.fill_bytes_with_seed_level(&mut buf).unwrap();
+        assert_eq!(seed_level, EsdmSeedLevel::Unseeded);
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        rng.try_next_u64().unwrap();
+
+        // once seeded, the seed level is not queried again
+        let status_calls = server.calls(EsdmOperation::Status);
+        rng.try_next_u64().unwrap();
+        rng.clone().try_next_u64().unwrap();
+        assert_eq!(server.calls(EsdmOperation::Status), status_calls);
+    }
+
+    #[test]
+    fn test_seed_rng() {
+        use rand_core::Rng;
+
+        fn is_crypto_rng<R: TryCryptoRng>(_: &R) {}
+
+        let _server = test_server();
+        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+        is_crypto_rng(&rng);
+
+        let mut a: chacha20::ChaCha20Rng = rng.seed_rng().unwrap();
+        let mut b: chacha20::ChaCha20Rng = SeedSource::PredictionResistant
+            .seed_rng(rng.connection())
+            .unwrap();
+        assert_ne!(a.next_u64(), b.next_u64());
+
+        let seed = SeedSource::default()
+            .fill_seed::<chacha20::ChaCha20Rng>(rng.connection())
+            .unwrap();
+        assert_ne!(seed, [0u8; 32]);
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let _server = test_server();
+        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+
+        let a = rng.generate_key::<32>().unwrap();
+        let b = rng.generate_key::<32>().unwrap();
+        assert_ne!(a.expose_secret(), b.expose_secret());
+        assert!(!a.is_locked());
+
+        let secret = rng.generate_secret(100).unwrap();
+        assert_eq!(secret.len(), 100);
+        assert!(!secret.expose_secret().ends_with(&[0u8; 32]));
+
+        // RLIMIT_MEMLOCK may be too low to lock anything, e.g. in containers
+        match rng.generate_locked_key::<32>() {
+            Ok(key) => assert!(key.is_locked()),
+            Err(err) => assert!(matches!(err, EsdmError::Os { .. })),
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_failed_fill_is_wiped() {
+        let server = test_server();
+        server.set_max_random_len(16);
+        server.set_latency(Duration::from_millis(200));
+        let mut rng =
+            EsdmRng::new(EsdmRngType::FullySeeded).with_retry_policy(RetryPolicy::fail_fast());
+
+        // the first chunk arrives, the second one fails
+        let mut buf = [0xffu8; 64];
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(300));
+                server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EIO);
+            });
+            rng.try_fill_bytes(&mut buf).unwrap_err();
+        });
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 2);
+        assert_eq!(buf, [0u8; 64]);
+    }
+
     #[test]
     fn test_status() {
-        esdm_rng_init_checked();
+        let _server = test_server();
+        let conn = EsdmConnection::new().unwrap();
 
         for _ in 0..100 {
-            let status = esdm_status_str().unwrap();
+            let status = conn.status_str().unwrap();
             println!("{status}");
         }
 
-        esdm_rng_fini();
+        let status = conn.status().unwrap();
+        assert!(status.seed_level().is_some());
+        assert!(status.entropy_level.is_some());
+        assert_eq!(status.seed_level(), conn.seed_level().ok());
+    }
+
+    #[test]
+    fn test_fork_independent_output() {
+        use std::io::Read;
+        use std::os::fd::FromRawFd;
+
+        let _server = test_server();
+        let mut buffered = BufferedEsdmRng::new().unwrap();
+        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+        // buffer random bytes before forking
+        let _ = buffered.try_next_u64().unwrap();
+
+        let mut children = vec![];
+        for _ in 0..4 {
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+
+            let pid = unsafe { libc::fork() };
+            assert!(pid >= 0);
+            if pid == 0 {
+                // child: report output of both generators, never return into the test harness
+                let ok = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    let mut out = [0u8; 32];
+                    buffered.try_fill_bytes(&mut out[..16]).unwrap();
+                    rng.try_fill_bytes(&mut out[16..]).unwrap();
+                    let written = unsafe { libc::write(fds[1], out.as_ptr().cast(), out.len()) };
+                    assert_eq!(written, 32);
+                }))
+                .is_ok();
+                unsafe { libc::_exit(i32::from(!ok)) };
+            }
+
+            unsafe { libc::close(fds[1]) };
+            children.push((pid, fds[0]));
+        }
+
+        let mut outputs = vec![];
+        for (pid, fd) in children {
+            let mut out = vec![];
+            unsafe { std::fs::File::from_raw_fd(fd) }
+                .read_to_end(&mut out)
+                .unwrap();
+
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &raw mut status, 0) }, pid);
+            assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+            assert_eq!(out.len(), 32);
+            outputs.push(out);
+        }
+
+        let mut out = vec![0u8; 32];
+        buffered.try_fill_bytes(&mut out[..16]).unwrap();
+        rng.try_fill_bytes(&mut out[16..]).unwrap();
+        outputs.push(out);
+
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                assert_ne!(a[..16], b[..16]);
+                assert_ne!(a[16..], b[16..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_connection() {
+        let _server = test_server();
+        let conn = EsdmConnection::new().unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let conn = conn.clone();
+                std::thread::spawn(move || {
+                    let mut rng = conn.rng(EsdmRngType::FullySeeded);
+                    for _ in 0..100 {
+                        let _ = rng.try_next_u64().unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(conn);
+
+        for t in threads {
+            t.join().unwrap();
+        }
     }
 
     // need to be root to run this test
     #[test]
     #[cfg(feature = "privileged_tests")]
     fn test_privileged_interface() {
-        // also need unprivileged interface for random bytes
-        esdm_rng_init_checked();
-        esdm_rng_init_priv_checked();
+        let _server = test_server();
+        let conn = EsdmPrivConnection::new().unwrap();
 
-        esdm_clear_pool().unwrap();
-        assert_eq!(esdm_get_entropy_count().unwrap(), 0);
-        esdm_add_to_entropy_count(64 * 8).unwrap();
-        esdm_reseed_crng().unwrap();
+        conn.clear_pool().unwrap();
+        assert_eq!(conn.entropy_count().unwrap(), 0);
+        conn.add_to_entropy_count(64 * 8).unwrap();
+        conn.reseed_crng().unwrap();
 
-        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+        let mut rng = conn.rng(EsdmRngType::FullySeeded);
 
         // don't do this in production: circular seeding
         let mut buf: [u8; 32] = [42; 32];
         rng.try_fill_bytes(&mut buf).unwrap();
-        esdm_clear_pool().unwrap();
-        esdm_add_entropy(&buf, u32::try_from(buf.len() * 8).unwrap()).unwrap();
-        assert!(esdm_get_entropy_count().unwrap() >= 32 * 8);
+        conn.clear_pool().unwrap();
+        conn.add_entropy(EntropyCredit::full(&buf)).unwrap();
+        assert!(conn.entropy_count().unwrap() >= 32 * 8);
     }
 }
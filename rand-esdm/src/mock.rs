@@ -0,0 +1,639 @@
+//! In-process stand-in for esdm-server, to test without ESDM installed
+//!
+//! [`MockEsdmServer`] serves the ESDM RPC protocol on Unix sockets in a
+//! temporary directory and points the `native` client backend of this process
+//! at them with [`EsdmClientConfig::apply`]. Its behaviour (seed level, entropy accounting, latency, failures)
+//! can be changed while it runs. Only one mock server exists per process at a
+//! time, [`MockEsdmServer::start`] blocks until a previous one is dropped.
+//!
+//! ```no_run
+//! use rand_core::TryRng;
+//! use rand_esdm::mock::MockEsdmServer;
+//! use rand_esdm::{EsdmRng, EsdmRngType, EsdmSeedLevel};
+//!
+//! let server = MockEsdmServer::start().unwrap();
+//! server.set_seed_level(EsdmSeedLevel::Unseeded);
+//!
+//! let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+//! assert!(rng.try_next_u64().is_err());
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use zeroize::Zeroizing;
+
+use crate::backend::proto;
+use crate::seed::SEED_HEADER_LEN;
+use crate::{EsdmClientConfig, EsdmOperation, EsdmSeedLevel, SeedFlags, kernel};
+
+/// number of seed bytes returned by the mock server
+const MOCK_SEED_LEN: usize = 64;
+
+static MOCK_LOCK: Mutex<()> = Mutex::new(());
+static MOCK_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug)]
+struct MockState {
+    seed_level: EsdmSeedLevel,
+    entropy_level: u32,
+    entropy_count: u32,
+    write_wakeup_thresh: u32,
+    min_reseed_secs: u32,
+    pool_size: u32,
+    latency: Duration,
+    privileged: bool,
+    max_random_len: usize,
+    failures: HashMap<EsdmOperation, (i32, Option<u32>)>,
+    calls: HashMap<EsdmOperation, u64>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        MockState {
+            seed_level: EsdmSeedLevel::FullySeeded,
+            entropy_level: 256,
+            entropy_count: 256,
+            write_wakeup_thresh: 128,
+            min_reseed_secs: 600,
+            pool_size: 4096,
+            latency: Duration::ZERO,
+            privileged: true,
+            max_random_len: proto::MAX_MSG_SIZE - 64,
+            failures: HashMap::new(),
+            calls: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Unpriv,
+    Priv,
+}
+
+/// Mock ESDM server, stops and restores the default socket paths on drop
+pub struct MockEsdmServer {
+    state: Arc<Mutex<MockState>>,
+    dir: PathBuf,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl MockEsdmServer {
+    /// starts a fully seeded mock server, which grants privileged access
+    pub fn start() -> io::Result<Self> {
+        let lock = MOCK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let dir = std::env::temp_dir().join(format!(
+            "rand-esdm-mock-{}-{}",
+            std::process::id(),
+            MOCK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+
+        let unpriv_path = dir.join("esdm-rpc-unpriv");
+        let priv_path = dir.join("esdm-rpc-priv");
+        let unpriv_listener = UnixListener::bind(&unpriv_path)?;
+        let priv_listener = UnixListener::bind(&priv_path)?;
+
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let threads = vec![
+            spawn_listener(unpriv_listener, Service::Unpriv, &state, &stop),
+            spawn_listener(priv_listener, Service::Priv, &state, &stop),
+        ];
+
+        EsdmClientConfig::new()
+            .with_unpriv_socket(unpriv_path)
+            .with_priv_socket(priv_path)
+            .apply();
+
+        Ok(MockEsdmServer {
+            state,
+            dir,
+            stop,
+            threads,
+            _lock: lock,
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// directory containing the sockets of the mock server
+    #[must_use]
+    pub fn socket_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// sets the reported seed level, random bytes are refused with `EAGAIN`
+    /// below the seed level of the requested type (esdm-server would block
+    /// instead), non-blocking requests are always answered
+    pub fn set_seed_level(&self, seed_level: EsdmSeedLevel) {
+        self.state().seed_level = seed_level;
+    }
+
+    /// sets the reported overall entropy level in bits
+    pub fn set_entropy_level(&self, entropy_level: u32) {
+        self.state().entropy_level = entropy_level;
+    }
+
+    /// sets the entropy count of the auxiliary pool in bits
+    pub fn set_entropy_count(&self, entropy_count: u32) {
+        self.state().entropy_count = entropy_count;
+    }
+
+    /// current entropy count of the auxiliary pool in bits
+    #[must_use]
+    pub fn entropy_count(&self) -> u32 {
+        self.state().entropy_count
+    }
+
+    /// sets the write wakeup threshold in bits
+    pub fn set_write_wakeup_thresh(&self, write_wakeup_thresh: u32) {
+        self.state().write_wakeup_thresh = write_wakeup_thresh;
+    }
+
+    /// delays every answer by `latency`
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// allows or refuses (with `EPERM`) all calls to the privileged service
+    pub fn set_privileged(&self, privileged: bool) {
+        self.state().privileged = privileged;
+    }
+
+    /// sets the maximum number of random bytes returned by one RPC, larger
+    /// requests are answered with fewer bytes
+    pub fn set_max_random_len(&self, max_random_len: usize) {
+        self.state().max_random_len = max_random_len;
+    }
+
+    /// makes the server unreachable for clients of this process (as if
+    /// esdm-server was stopped), or reachable again
+    pub fn set_reachable(&self, reachable: bool) {
+        let prefix = if reachable { "" } else { "unreachable-" };
+        EsdmClientConfig::new()
+            .with_unpriv_socket(self.dir.join(format!("{prefix}esdm-rpc-unpriv")))
+            .with_priv_socket(self.dir.join(format!("{prefix}esdm-rpc-priv")))
+            .apply();
+    }
+
+    /// answers the next `times` calls of `op` with the error `code` (a negative errno)
+    pub fn fail_next(&self, op: EsdmOperation, code: i32, times: u32) {
+        self.state().failures.insert(op, (code, Some(times)));
+    }
+
+    /// answers all calls of `op` with the error `code` (a negative errno)
+    pub fn fail_always(&self, op: EsdmOperation, code: i32) {
+        self.state().failures.insert(op, (code, None));
+    }
+
+    /// removes all failures configured with `fail_next` or `fail_always`
+    pub fn clear_failures(&self) {
+        self.state().failures.clear();
+    }
+
+    /// number of calls of `op` received so far
+    #[must_use]
+    pub fn calls(&self, op: EsdmOperation) -> u64 {
+        self.state().calls.get(&op).copied().unwrap_or(0)
+    }
+}
+
+impl Drop for MockEsdmServer {
+    fn drop(&mut self) {
+        EsdmClientConfig::new().apply();
+
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the listeners blocked in accept()
+        for socket in ["esdm-rpc-unpriv", "esdm-rpc-priv"] {
+            let _ = UnixStream::connect(self.dir.join(socket));
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn spawn_listener(
+    listener: UnixListener,
+    service: Service,
+    state: &Arc<Mutex<MockState>>,
+    stop: &Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let state = Arc::clone(state);
+    let stop = Arc::clone(stop);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            if let Ok(stream) = stream {
+                let state = Arc::clone(&state);
+                std::thread::spawn(move || serve_connection(stream, service, &state));
+            }
+        }
+    })
+}
+
+fn serve_connection(mut stream: UnixStream, service: Service, state: &Mutex<MockState>) {
+    loop {
+        let mut header = [0u8; proto::CS_HEADER_LEN];
+        if stream.read_exact(&mut header).is_err() {
+            return;
+        }
+        let header = proto::RequestHeader::from_bytes(&header);
+
+        let Ok(len) = usize::try_from(header.message_length) else {
+            return;
+        };
+        if len > proto::MAX_MSG_SIZE {
+            return;
+        }
+        let mut body = vec![0u8; len];
+        if stream.read_exact(&mut body).is_err() {
+            return;
+        }
+
+        let (status_code, response) = match proto::Message::decode(&body) {
+            Some(request) => {
+                let response = handle(service, header.method_index, &request, state);
+                (proto::STATUS_SUCCESS, response)
+            }
+            None => (proto::STATUS_SERVICE_FAILED, Zeroizing::new(Vec::new())),
+        };
+
+        let response_header = proto::ResponseHeader {
+            status_code,
+            method_index: header.method_index,
+            message_length: u32::try_from(response.len()).unwrap(),
+            request_id: header.request_id,
+        };
+        if stream.write_all(&response_header.to_bytes()).is_err()
+            || stream.write_all(&response).is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn operation(service: Service, method: u32) -> Option<EsdmOperation> {
+    use proto::{privileged, unpriv};
+
+    let op = match (service, method) {
+        (Service::Unpriv, unpriv::STATUS) => EsdmOperation::Status,
+        (Service::Unpriv, unpriv::GET_RANDOM_BYTES_FULL) => EsdmOperation::GetRandomBytesFull,
+        (Service::Unpriv, unpriv::GET_RANDOM_BYTES_PR) => EsdmOperation::GetRandomBytesPr,
+        (Service::Unpriv, unpriv::GET_RANDOM_BYTES_MIN) => EsdmOperation::GetRandomBytesMin,
+        (Service::Unpriv, unpriv::GET_RANDOM_BYTES) => EsdmOperation::GetRandomBytes,
+        (Service::Unpriv, unpriv::GET_SEED) => EsdmOperation::GetSeed,
+        (Service::Unpriv, unpriv::WRITE_DATA) => EsdmOperation::WriteData,
+        (Service::Unpriv, unpriv::RND_GET_ENT_CNT) => EsdmOperation::GetEntropyCount,
+        (Service::Unpriv, unpriv::GET_WRITE_WAKEUP_THRESH) => EsdmOperation::GetWriteWakeupThresh,
+        (Service::Unpriv, unpriv::GET_MIN_RESEED_SECS) => EsdmOperation::GetMinReseedSecs,
+        (Service::Unpriv, unpriv::RND_GET_POOLSIZE) => EsdmOperation::GetPoolSize,
+        (Service::Unpriv, unpriv::GET_ENT_LVL) => EsdmOperation::GetEntropyLevel,
+        (Service::Unpriv, unpriv::JENT_STATUS) => EsdmOperation::JentStatus,
+        (Service::Priv, privileged::RND_ADD_TO_ENT_CNT) => EsdmOperation::AddToEntropyCount,
+        (Service::Priv, privileged::RND_ADD_ENTROPY) => EsdmOperation::AddEntropy,
+        (Service::Priv, privileged::RND_CLEAR_POOL) => EsdmOperation::ClearPool,
+        (Service::Priv, privileged::RND_RESEED_CRNG) => EsdmOperation::ReseedCrng,
+        (Service::Priv, privileged::SET_WRITE_WAKEUP_THRESH) => EsdmOperation::SetWriteWakeupThresh,
+        (Service::Priv, privileged::SET_MIN_RESEED_SECS) => EsdmOperation::SetMinReseedSecs,
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+fn fill_random(buf: &mut [u8]) -> Result<(), i32> {
+    kernel::fill(buf).map_err(|err| -err.raw_os_error().unwrap_or(libc::EIO))
+}
+
+fn status_text(state: &MockState) -> String {
+    format!(
+        "ESDM library version: mock\n \
+         DRNG name: ChaCha20 DRNG\n \
+         DRNG security strength in bits: 256\n \
+         Number of DRNG instances: 1\n \
+         Standards compliance: SP800-90C\n \
+         ESDM minimally seeded: {}\n \
+         ESDM fully seeded: {}\n\
+         ESDM entropy level: {}\n",
+        state.seed_level >= EsdmSeedLevel::MinimallySeeded,
+        state.seed_level == EsdmSeedLevel::FullySeeded,
+        state.entropy_level
+    )
+}
+
+/// answers a seed request with `MOCK_SEED_LEN` bytes, refused with `EAGAIN`
+/// instead of blocking while not (fully) seeded
+fn seed_response(state: &MockState, request: &proto::Message) -> Zeroizing<Vec<u8>> {
+    let len = request.uint32(1).unwrap_or(0) as usize;
+    let flags = SeedFlags::from_bits(request.uint32(2).unwrap_or(0));
+    let required = SEED_HEADER_LEN + MOCK_SEED_LEN;
+
+    if len < required {
+        let required = u64::try_from(required).unwrap();
+        return proto::Encoder::new()
+            .int32(1, -libc::EMSGSIZE)
+            .bytes(2, &required.to_ne_bytes())
+            .finish();
+    }
+
+    let needed = if flags.contains(SeedFlags::FULLY_SEEDED) {
+        EsdmSeedLevel::FullySeeded
+    } else {
+        EsdmSeedLevel::MinimallySeeded
+    };
+    if state.seed_level < needed {
+        return proto::Encoder::new().int32(1, -libc::EAGAIN).finish();
+    }
+
+    let mut seed = vec![0u8; required];
+    let entropy_bits = u64::from(state.entropy_level).min(MOCK_SEED_LEN as u64 * 8);
+    seed[0..8].copy_from_slice(&u64::try_from(required).unwrap().to_ne_bytes());
+    seed[8..16].copy_from_slice(&entropy_bits.to_ne_bytes());
+    if let Err(code) = fill_random(&mut seed[SEED_HEADER_LEN..]) {
+        return proto::Encoder::new().int32(1, code).finish();
+    }
+
+    proto::Encoder::new()
+        .int32(1, i32::try_from(required).unwrap())
+        .bytes(2, &seed)
+        .finish()
+}
+
+/// answers a single request, returns the encoded response message
+fn handle(
+    service: Service,
+    method: u32,
+    request: &proto::Message,
+    state: &Mutex<MockState>,
+) -> Zeroizing<Vec<u8>> {
+    let Some(op) = operation(service, method) else {
+        return proto::Encoder::new().int32(1, -libc::EOPNOTSUPP).finish();
+    };
+
+    let latency = {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state.calls.entry(op).or_default() += 1;
+        state.latency
+    };
+    if !latency.is_zero() {
+        std::thread::sleep(latency);
+    }
+
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if service == Service::Priv && !state.privileged {
+        return proto::Encoder::new().int32(1, -libc::EPERM).finish();
+    }
+
+    if let Some((code, remaining)) = state.failures.get_mut(&op) {
+        let code = *code;
+        match remaining {
+            Some(0) => {}
+            Some(n) => {
+                *n -= 1;
+                return proto::Encoder::new().int32(1, code).finish();
+            }
+            None => return proto::Encoder::new().int32(1, code).finish(),
+        }
+    }
+
+    let ok = proto::Encoder::new().int32(1, 0);
+    match op {
+        EsdmOperation::Status => ok.bytes(2, status_text(&state).as_bytes()).finish(),
+        EsdmOperation::JentStatus => ok.bytes(2, b"Jitter RNG: mock\n").finish(),
+        EsdmOperation::GetRandomBytesFull
+        | EsdmOperation::GetRandomBytesPr
+        | EsdmOperation::GetRandomBytesMin
+        | EsdmOperation::GetRandomBytes => {
+            let needed = match op {
+                EsdmOperation::GetRandomBytesMin => EsdmSeedLevel::MinimallySeeded,
+                EsdmOperation::GetRandomBytes => EsdmSeedLevel::Unseeded,
+                _ => EsdmSeedLevel::FullySeeded,
+            };
+            if state.seed_level < needed {
+                return proto::Encoder::new().int32(1, -libc::EAGAIN).finish();
+            }
+            let len = request.uint32(1).unwrap_or(0) as usize;
+            let mut random = vec![0u8; len.min(state.max_random_len)];
+            match fill_random(&mut random) {
+                Ok(()) => proto::Encoder::new()
+                    .int32(1, i32::try_from(random.len()).unwrap())
+                    .bytes(2, &random)
+                    .finish(),
+                Err(code) => proto::Encoder::new().int32(1, code).finish(),
+            }
+        }
+        EsdmOperation::GetSeed => seed_response(&state, request),
+        EsdmOperation::GetEntropyCount => ok.uint32(2, state.entropy_count).finish(),
+        EsdmOperation::GetWriteWakeupThresh => ok.uint32(2, state.write_wakeup_thresh).finish(),
+        EsdmOperation::GetMinReseedSecs => ok.uint32(2, state.min_reseed_secs).finish(),
+        EsdmOperation::GetPoolSize => ok.uint32(2, state.pool_size).finish(),
+        EsdmOperation::GetEntropyLevel => ok.uint32(2, state.entropy_level).finish(),
+        EsdmOperation::SetWriteWakeupThresh => {
+            state.write_wakeup_thresh = request.uint32(1).unwrap_or(0);
+            ok.finish()
+        }
+        EsdmOperation::SetMinReseedSecs => {
+            state.min_reseed_secs = request.uint32(1).unwrap_or(0);
+            ok.finish()
+        }
+        EsdmOperation::AddEntropy => {
+            let data_bits = request
+                .bytes(1)
+                .map_or(0, |data| u32::try_from(data.len() * 8).unwrap_or(u32::MAX));
+            let bits = request.uint32(2).unwrap_or(0).min(data_bits);
+            state.entropy_count = state.entropy_count.saturating_add(bits);
+            ok.finish()
+        }
+        EsdmOperation::AddToEntropyCount => {
+            let bits = request.uint32(1).unwrap_or(0);
+            state.entropy_count = state.entropy_count.saturating_add(bits);
+            ok.finish()
+        }
+        EsdmOperation::ClearPool => {
+            state.entropy_count = 0;
+            ok.finish()
+        }
+        _ => ok.finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        EntropyCredit, EsdmConnection, EsdmPrivConnection, EsdmRng, EsdmRngType, EsdmService,
+        RetryPolicy,
+    };
+    use rand_core::TryRng;
+
+    #[test]
+    fn test_mock_random_and_status() {
+        let server = MockEsdmServer::start().unwrap();
+
+        let mut rng = EsdmRng::new(EsdmRngType::FullySeeded);
+        let mut buf = [0u8; 64];
+        rng.try_fill_bytes(&mut buf).unwrap();
+        assert_ne!(buf, [0u8; 64]);
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 1);
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        server.set_entropy_level(130);
+        let status = rng.connection().status().unwrap();
+        assert_eq!(status.seed_level(), Some(EsdmSeedLevel::MinimallySeeded));
+        assert_eq!(status.entropy_level, Some(130));
+
+        let err = rng.try_fill_bytes(&mut buf).unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EAGAIN));
+    }
+
+    #[test]
+    fn test_mock_chunking() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_max_random_len(100);
+
+        let mut rng = EsdmRng::new(EsdmRngType::PredictionResistant)
+            .with_max_chunk_size(NonZeroUsize::new(256).unwrap());
+        assert_eq!(rng.connection().max_chunk_size(), 256);
+
+        // every RPC asks for up to 256 bytes and gets 100 of them
+        let mut buf = vec![0u8; 1000];
+        rng.try_fill_bytes(&mut buf).unwrap();
+        assert!(!buf.ends_with(&[0u8; 32]));
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesPr), 10);
+    }
+
+    #[test]
+    fn test_mock_failures() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmConnection::new().unwrap();
+
+        server.fail_next(EsdmOperation::GetEntropyCount, -libc::EINTR, 2);
+        assert_eq!(conn.entropy_count().unwrap(), 256);
+        assert_eq!(server.calls(EsdmOperation::GetEntropyCount), 3);
+
+        server.fail_always(EsdmOperation::WriteData, -libc::EIO);
+        let err = conn.write_data(b"data").unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EIO));
+        server.clear_failures();
+        conn.write_data(b"data").unwrap();
+
+        let conn = conn.with_retry_policy(RetryPolicy::fail_fast());
+        server.fail_next(EsdmOperation::GetEntropyCount, -libc::EINTR, 1);
+        assert_eq!(conn.entropy_count().unwrap_err().attempts(), 1);
+    }
+
+    #[test]
+    fn test_mock_privileged() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmPrivConnection::new().unwrap();
+
+        conn.clear_pool().unwrap();
+        conn.add_entropy(EntropyCredit::full(&[0u8; 8])).unwrap();
+        assert_eq!(server.entropy_count(), 64);
+
+        server.set_privileged(false);
+        let err = conn.add_to_entropy_count(8).unwrap_err();
+        assert_eq!(err.service(), EsdmService::Privileged);
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_mock_tunables() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmPrivConnection::new().unwrap();
+
+        assert_eq!(conn.pool_size().unwrap(), 4096);
+        server.set_entropy_level(200);
+        assert_eq!(conn.entropy_level().unwrap(), 200);
+
+        conn.set_write_wakeup_thresh(512).unwrap();
+        assert_eq!(conn.write_wakeup_thresh().unwrap(), 512);
+        conn.set_min_reseed_secs(60).unwrap();
+        assert_eq!(conn.min_reseed_secs().unwrap(), 60);
+
+        server.set_privileged(false);
+        let err = conn.set_min_reseed_secs(0).unwrap_err();
+        assert_eq!(err.operation(), EsdmOperation::SetMinReseedSecs);
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(conn.min_reseed_secs().unwrap(), 60);
+    }
+
+    #[test]
+    fn test_mock_seed() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmConnection::new().unwrap();
+
+        let mut buf = [0u8; 256];
+        let info = conn.get_seed(&mut buf, SeedFlags::BLOCKING).unwrap();
+        assert_eq!(info.len, MOCK_SEED_LEN);
+        assert_eq!(info.entropy_bits, 256);
+        assert!(!info.is_full_entropy());
+        assert!(buf[..info.len].iter().any(|b| *b != 0));
+        assert!(buf[info.len..].iter().all(|b| *b == 0));
+
+        let err = conn
+            .get_seed(&mut [0u8; 32], SeedFlags::BLOCKING)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::EsdmError::BufferTooSmall { required: 80, .. }
+        ));
+
+        // non-blocking requests are not retried
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        conn.get_seed(&mut buf, SeedFlags::NON_BLOCKING).unwrap();
+        let err = conn
+            .get_seed(&mut buf, SeedFlags::NON_BLOCKING | SeedFlags::FULLY_SEEDED)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(err.attempts(), 1);
+    }
+
+    #[test]
+    fn test_mock_rng_types() {
+        let server = MockEsdmServer::start().unwrap();
+        let conn = EsdmConnection::new()
+            .unwrap()
+            .with_retry_policy(RetryPolicy::fail_fast());
+        let mut buf = [0u8; 16];
+
+        server.set_seed_level(EsdmSeedLevel::Unseeded);
+        let seed_level = conn
+            .fill_bytes_with_seed_level(EsdmRngType::NonBlocking, &mut buf)
+            .unwrap();
+        assert_eq!(seed_level, EsdmSeedLevel::Unseeded);
+        let err = conn
+            .fill_bytes(EsdmRngType::MinimallySeeded, &mut buf)
+            .unwrap_err();
+        assert_eq!(err.operation(), EsdmOperation::GetRandomBytesMin);
+
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        conn.fill_bytes(EsdmRngType::MinimallySeeded, &mut buf)
+            .unwrap();
+        assert!(conn.fill_bytes(EsdmRngType::FullySeeded, &mut buf).is_err());
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytes), 1);
+    }
+}
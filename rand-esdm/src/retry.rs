@@ -0,0 +1,315 @@
+//! Retry behaviour of ESDM calls
+//!
+//! Every call to ESDM is run under a [`RetryPolicy`]: failed attempts of a
+//! retryable [`RetryClass`] are repeated with exponential backoff, until the
+//! maximum number of attempts or the deadline is reached. The policy is set
+//! process-wide with [`esdm_set_retry_policy`] or per connection and generator
+//! (e.g. [`EsdmConnection::with_retry_policy`](crate::EsdmConnection::with_retry_policy)).
+
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{EsdmError, cancel, kernel};
+
+static GLOBAL_POLICY: RwLock<RetryPolicy> = RwLock::new(RetryPolicy::new());
+
+/// kind of failure, decides if a failed call is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// temporary condition like `EAGAIN`, `EINTR` or `EBUSY`
+    Transient,
+
+    /// esdm-server is not reachable, e.g. still starting or restarting
+    Unavailable,
+
+    /// fewer random bytes than requested were returned
+    ShortRead,
+
+    /// every other error, e.g. missing privileges or invalid arguments
+    Fatal,
+}
+
+impl RetryClass {
+    const fn bit(self) -> u8 {
+        match self {
+            RetryClass::Transient => 1 << 0,
+            RetryClass::Unavailable => 1 << 1,
+            RetryClass::ShortRead => 1 << 2,
+            RetryClass::Fatal => 1 << 3,
+        }
+    }
+}
+
+/// when and how often failed ESDM calls are repeated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    deadline: Option<Duration>,
+    retryable: u8,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// 5 attempts, backoff from 1 ms up to 100 ms with jitter, retries
+    /// transient, unavailable and short read failures
+    #[must_use]
+    pub const fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+            jitter: true,
+            deadline: None,
+            retryable: RetryClass::Transient.bit()
+                | RetryClass::Unavailable.bit()
+                | RetryClass::ShortRead.bit(),
+        }
+    }
+
+    /// single attempt, every error is returned at once
+    #[must_use]
+    pub const fn fail_fast() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// keeps retrying with up to 1 s backoff until `deadline` passed, e.g. to
+    /// wait for esdm-server during boot
+    #[must_use]
+    pub const fn patient(deadline: Duration) -> Self {
+        Self::new()
+            .with_max_attempts(u32::MAX)
+            .with_backoff(Duration::from_millis(10), Duration::from_secs(1))
+            .with_deadline(deadline)
+    }
+
+    /// sets the maximum number of attempts, including the first one (at least 1)
+    #[must_use]
+    pub const fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = if max_attempts == 0 { 1 } else { max_attempts };
+        self
+    }
+
+    /// sets the delay after the first failed attempt, doubled after every
+    /// further one up to `max`
+    #[must_use]
+    pub const fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// randomizes every delay between half and all of the backoff, so that
+    /// many clients don't retry in lockstep
+    #[must_use]
+    pub const fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// sets the maximum time spent on all attempts of one call
+    #[must_use]
+    pub const fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// sets if failures of `class` are retried
+    #[must_use]
+    pub const fn with_retryable(mut self, class: RetryClass, retryable: bool) -> Self {
+        if retryable {
+            self.retryable |= class.bit();
+        } else {
+            self.retryable &= !class.bit();
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    #[must_use]
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    #[must_use]
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    #[must_use]
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+
+    #[must_use]
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    #[must_use]
+    pub fn is_retryable(&self, class: RetryClass) -> bool {
+        self.retryable & class.bit() != 0
+    }
+
+    /// reason, why this policy cannot be used, if any
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if self.initial_backoff > self.max_backoff {
+            return Err("initial backoff exceeds the maximum backoff");
+        }
+        if self.deadline.is_some_and(|deadline| deadline.is_zero()) {
+            return Err("deadline must not be zero");
+        }
+        Ok(())
+    }
+
+    /// delay before the next attempt
+    fn delay(&self, backoff: Duration) -> Duration {
+        if !self.jitter || backoff.is_zero() {
+            return backoff;
+        }
+
+        // the jitter needs no cryptographic quality, a failing getrandom just disables it
+        let random = kernel::u32().unwrap_or(u32::MAX);
+        let half = backoff / 2;
+        half + half.mul_f64(f64::from(random) / f64::from(u32::MAX))
+    }
+
+    /// runs `call` with the attempt number (starting at 1) until it succeeds or
+    /// the policy gives up, returns the last error then
+    ///
+    /// Once the cancellation token of the current thread is cancelled, the
+    /// failure of the interrupted attempt is reported as [`EsdmError::Cancelled`].
+    pub(crate) fn run<T>(
+        &self,
+        mut call: impl FnMut(u32) -> Result<T, EsdmError>,
+    ) -> Result<T, EsdmError> {
+        let start = Instant::now();
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            let err = match call(attempt) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let cancelled = EsdmError::Cancelled {
+                op: err.operation(),
+            };
+            if cancel::is_cancelled() {
+                return Err(cancelled);
+            }
+
+            if attempt >= self.max_attempts || !self.is_retryable(err.retry_class()) {
+                return Err(err);
+            }
+
+            let mut delay = self.delay(backoff);
+            if let Some(deadline) = self.deadline {
+                let left = deadline.saturating_sub(start.elapsed());
+                if left.is_zero() {
+                    return Err(err);
+                }
+                delay = delay.min(left);
+            }
+            if cancel::sleep(delay) {
+                return Err(cancelled);
+            }
+
+            backoff = backoff.saturating_mul(2).min(self.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+/// sets the retry policy of all connections without their own policy
+pub fn esdm_set_retry_policy(policy: RetryPolicy) {
+    *GLOBAL_POLICY
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = policy;
+}
+
+/// returns the retry policy of all connections without their own policy
+#[must_use]
+pub fn esdm_retry_policy() -> RetryPolicy {
+    *GLOBAL_POLICY.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EsdmOperation;
+
+    fn rpc_error(code: i32, attempts: u32) -> EsdmError {
+        EsdmError::Rpc {
+            op: EsdmOperation::GetEntropyCount,
+            code,
+            attempts,
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_run() {
+        let policy = RetryPolicy::new().with_backoff(Duration::ZERO, Duration::ZERO);
+
+        let mut calls = 0;
+        let result = policy.run(|attempt| {
+            calls += 1;
+            if attempt < 3 {
+                Err(rpc_error(-libc::EAGAIN, attempt))
+            } else {
+                Ok(attempt)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls, 3);
+
+        let err = policy
+            .run::<()>(|attempt| Err(rpc_error(-libc::EAGAIN, attempt)))
+            .unwrap_err();
+        assert_eq!(err.attempts(), 5);
+
+        // not retryable by default
+        let err = policy
+            .run::<()>(|attempt| Err(rpc_error(-libc::EPERM, attempt)))
+            .unwrap_err();
+        assert_eq!(err.attempts(), 1);
+        let policy = policy.with_retryable(RetryClass::Fatal, true);
+        let err = policy
+            .run::<()>(|attempt| Err(rpc_error(-libc::EPERM, attempt)))
+            .unwrap_err();
+        assert_eq!(err.attempts(), 5);
+
+        let err = RetryPolicy::fail_fast()
+            .run::<()>(|attempt| Err(rpc_error(-libc::EAGAIN, attempt)))
+            .unwrap_err();
+        assert_eq!(err.attempts(), 1);
+    }
+
+    #[test]
+    fn test_retry_policy_deadline() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(u32::MAX)
+            .with_backoff(Duration::from_millis(20), Duration::from_millis(20))
+            .with_deadline(Duration::from_millis(100));
+
+        let start = Instant::now();
+        let err = policy
+            .run::<()>(|attempt| Err(rpc_error(-libc::ECONNREFUSED, attempt)))
+            .unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(err.attempts() > 1 && err.attempts() < 20);
+    }
+}
@@ -0,0 +1,279 @@
+//! Secret buffers for key material, see [`SecretKey`] and [`SecretBytes`]
+
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::io;
+use std::ptr::NonNull;
+use std::slice;
+
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+fn page_size() -> usize {
+    usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).unwrap_or(4096)
+}
+
+/// heap memory holding secret bytes, zeroed before it is freed
+struct SecretBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    // allocation behind `ptr`, empty buffers own no memory
+    layout: Option<Layout>,
+    locked: bool,
+}
+
+// SecretBuf owns its memory exclusively, like a Box<[u8]>
+unsafe impl Send for SecretBuf {}
+unsafe impl Sync for SecretBuf {}
+
+impl SecretBuf {
+    fn alloc(layout: Layout, len: usize) -> Self {
+        let ptr = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        SecretBuf {
+            ptr,
+            len,
+            layout: Some(layout),
+            locked: false,
+        }
+    }
+
+    fn empty(locked: bool) -> Self {
+        SecretBuf {
+            ptr: NonNull::dangling(),
+            len: 0,
+            layout: None,
+            locked,
+        }
+    }
+
+    /// `len` zero bytes, panics if `len` exceeds `isize::MAX` like `Vec`
+    fn new(len: usize) -> Self {
+        if len == 0 {
+            return Self::empty(false);
+        }
+        Self::alloc(Layout::array::<u8>(len).expect("capacity overflow"), len)
+    }
+
+    /// `len` zero bytes on pages of their own, locked into RAM
+    fn new_locked(len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(Self::empty(true));
+        }
+
+        // munlock works on whole pages, sharing one with another locked
+        // buffer would unlock it once the first of both is dropped
+        let page = page_size();
+        let size = len
+            .checked_next_multiple_of(page)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let layout = Layout::from_size_align(size, page)
+            .map_err(|err| io::Error::new(io::ErrorKind::OutOfMemory, err))?;
+
+        let mut buf = Self::alloc(layout, len);
+        let ptr = buf.ptr.as_ptr().cast();
+        if unsafe { libc::mlock(ptr, size) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.locked = true;
+
+        // best effort, keeping the secret out of core dumps is no requirement
+        unsafe { libc::madvise(ptr, size, libc::MADV_DONTDUMP) };
+
+        Ok(buf)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for SecretBuf {
+    fn drop(&mut self) {
+        self.as_mut_slice().zeroize();
+
+        if let Some(layout) = self.layout {
+            if self.locked {
+                unsafe { libc::munlock(self.ptr.as_ptr().cast(), layout.size()) };
+            }
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), layout) };
+        }
+    }
+}
+
+/// Key of `N` secret bytes, zeroed on drop
+///
+/// Random bytes fetched into ordinary arrays and `Vec`s stay in memory after
+/// use, are copied whenever the array is moved and may be swapped to disk. A
+/// `SecretKey` keeps its bytes in a heap allocation, which never moves and is
+/// zeroed before it is freed. Locked keys, e.g. of
+/// [`EsdmRng::generate_locked_key`](crate::EsdmRng::generate_locked_key), get
+/// pages of their own, which are pinned in RAM with `mlock` and left out of
+/// core dumps. How much memory a process may lock is limited by
+/// `RLIMIT_MEMLOCK`.
+pub struct SecretKey<const N: usize> {
+    buf: SecretBuf,
+}
+
+impl<const N: usize> SecretKey<N> {
+    /// key of zero bytes, to be filled through [`expose_secret_mut`](Self::expose_secret_mut)
+    #[must_use]
+    pub fn zeroed() -> Self {
+        SecretKey {
+            buf: SecretBuf::new(N),
+        }
+    }
+
+    /// like [`zeroed`](Self::zeroed), in memory locked with `mlock`, fails if
+    /// the memory cannot be locked
+    pub fn zeroed_locked() -> io::Result<Self> {
+        Ok(SecretKey {
+            buf: SecretBuf::new_locked(N)?,
+        })
+    }
+
+    /// true, if the key is locked into RAM
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.buf.locked
+    }
+
+    #[must_use]
+    pub fn expose_secret(&self) -> &[u8; N] {
+        self.buf.as_slice().try_into().unwrap()
+    }
+
+    #[must_use]
+    pub fn expose_secret_mut(&mut self) -> &mut [u8; N] {
+        self.buf.as_mut_slice().try_into().unwrap()
+    }
+}
+
+impl<const N: usize> fmt::Debug for SecretKey<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("len", &N)
+            .field("locked", &self.is_locked())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const N: usize> ZeroizeOnDrop for SecretKey<N> {}
+
+/// Secret bytes of a length chosen at runtime, zeroed on drop
+///
+/// Kept and optionally locked into RAM like a [`SecretKey`].
+pub struct SecretBytes {
+    buf: SecretBuf,
+}
+
+impl SecretBytes {
+    /// `len` zero bytes, to be filled through [`expose_secret_mut`](Self::expose_secret_mut)
+    #[must_use]
+    pub fn zeroed(len: usize) -> Self {
+        SecretBytes {
+            buf: SecretBuf::new(len),
+        }
+    }
+
+    /// like [`zeroed`](Self::zeroed), in memory locked with `mlock`, fails if
+    /// the memory cannot be locked
+    pub fn zeroed_locked(len: usize) -> io::Result<Self> {
+        Ok(SecretBytes {
+            buf: SecretBuf::new_locked(len)?,
+        })
+    }
+
+    /// true, if the bytes are locked into RAM
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.buf.locked
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buf.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buf.len == 0
+    }
+
+    #[must_use]
+    pub fn expose_secret(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    #[must_use]
+    pub fn expose_secret_mut(&mut self) -> &mut [u8] {
+        self.buf.as_mut_slice()
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretBytes")
+            .field("len", &self.len())
+            .field("locked", &self.is_locked())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ZeroizeOnDrop for SecretBytes {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_key() {
+        let mut key = SecretKey::<32>::zeroed();
+        assert_eq!(key.expose_secret(), &[0u8; 32]);
+        key.expose_secret_mut().fill(0xa5);
+        assert_eq!(key.expose_secret(), &[0xa5; 32]);
+        assert!(!key.is_locked());
+        assert_eq!(
+            format!("{key:?}"),
+            "SecretKey { len: 32, locked: false, .. }"
+        );
+
+        let empty = SecretKey::<0>::zeroed();
+        assert!(empty.expose_secret().is_empty());
+    }
+
+    #[test]
+    fn test_secret_bytes() {
+        let mut bytes = SecretBytes::zeroed(100);
+        assert_eq!(bytes.len(), 100);
+        bytes.expose_secret_mut()[99] = 1;
+        assert_eq!(bytes.expose_secret()[99], 1);
+        assert_eq!(
+            format!("{bytes:?}"),
+            "SecretBytes { len: 100, locked: false, .. }"
+        );
+
+        assert!(SecretBytes::zeroed(0).is_empty());
+    }
+
+    #[test]
+    fn test_locked() {
+        // RLIMIT_MEMLOCK may be too low to lock anything, e.g. in containers
+        let Ok(mut key) = SecretKey::<64>::zeroed_locked() else {
+            return;
+        };
+        assert!(key.is_locked());
+        key.expose_secret_mut().fill(1);
+
+        // a second buffer gets pages of its own, dropping it keeps the key locked
+        if let Ok(bytes) = SecretBytes::zeroed_locked(16) {
+            let key_page = key.buf.ptr.as_ptr() as usize / page_size();
+            let bytes_page = bytes.buf.ptr.as_ptr() as usize / page_size();
+            assert_ne!(key_page, bytes_page);
+        }
+        assert_eq!(key.expose_secret(), &[1; 64]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Seed data with entropy accounting
+//!
+//! Unlike random bytes from the DRNG, `esdm_rpcc_get_seed` returns the data
+//! collected from the entropy sources together with the entropy it was credited
+//! with, similar to `getrandom(GRND_RANDOM)`. It is meant to seed other DRBGs,
+//! e.g. in an HSM. ESDM prefixes the data with two native-endian `u64`: the
+//! number of bytes written including this header, and the collected entropy
+//! in bits. [`EsdmConnection::get_seed`](crate::EsdmConnection::get_seed)
+//! strips the header and returns its values as [`SeedInfo`].
+//!
+//! Generators implementing [`SeedableRng`] are seeded from DRNG output instead,
+//! selected with [`SeedSource`].
+
+use std::ops::BitOr;
+
+use rand_core::SeedableRng;
+use zeroize::Zeroize;
+
+use crate::{EsdmConnection, EsdmError, EsdmOperation, EsdmRngType};
+
+/// length of the header in front of the seed data
+pub(crate) const SEED_HEADER_LEN: usize = 16;
+
+/// flags of a seed request, combine them with `|`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SeedFlags(u32);
+
+impl SeedFlags {
+    /// blocks until the entropy sources deliver data
+    pub const BLOCKING: SeedFlags = SeedFlags(0);
+
+    /// fails with `EAGAIN` instead of blocking
+    pub const NON_BLOCKING: SeedFlags = SeedFlags(1 << 0);
+
+    /// only returns data once the entropy sources together deliver the full
+    /// security strength of the DRNG
+    pub const FULLY_SEEDED: SeedFlags = SeedFlags(1 << 1);
+
+    #[cfg(feature = "mock")]
+    pub(crate) const fn from_bits(bits: u32) -> Self {
+        SeedFlags(bits)
+    }
+
+    /// flags as passed to `esdm_rpcc_get_seed`
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// true, if all flags of `other` are set
+    #[must_use]
+    pub const fn contains(self, other: SeedFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SeedFlags {
+    type Output = SeedFlags;
+
+    fn bitor(self, rhs: SeedFlags) -> SeedFlags {
+        SeedFlags(self.0 | rhs.0)
+    }
+}
+
+/// Metadata of seed data returned by ESDM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SeedInfo {
+    /// number of seed bytes at the start of the buffer
+    pub len: usize,
+
+    /// entropy in bits the entropy sources credited the seed data with
+    pub entropy_bits: u64,
+}
+
+impl SeedInfo {
+    /// true, if every bit of the seed data was credited with a bit of entropy
+    #[must_use]
+    pub fn is_full_entropy(&self) -> bool {
+        self.entropy_bits >= self.len as u64 * 8
+    }
+}
+
+/// ESDM mode the seeds of other generators are taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SeedSource {
+    /// output of the fully seeded DRNG, suitable for most generators
+    #[default]
+    FullySeeded,
+
+    /// prediction resistant output, every seed is backed by fresh entropy
+    PredictionResistant,
+}
+
+impl SeedSource {
+    /// random number type used to fetch seeds
+    #[must_use]
+    pub fn rng_type(self) -> EsdmRngType {
+        match self {
+            SeedSource::FullySeeded => EsdmRngType::FullySeeded,
+            SeedSource::PredictionResistant => EsdmRngType::PredictionResistant,
+        }
+    }
+
+    /// returns a seed for `R` from ESDM
+    pub fn fill_seed<R: SeedableRng>(self, conn: &EsdmConnection) -> Result<R::Seed, EsdmError> {
+        let mut seed = R::Seed::default();
+        conn.fill_bytes(self.rng_type(), seed.as_mut())?;
+
+        Ok(seed)
+    }
+
+    /// creates a generator `R` seeded from ESDM
+    pub fn seed_rng<R: SeedableRng>(self, conn: &EsdmConnection) -> Result<R, EsdmError> {
+        seed_rng(conn, self.rng_type())
+    }
+}
+
+/// creates a generator `R` seeded with random bytes of `rng_type`
+pub(crate) fn seed_rng<R: SeedableRng>(
+    conn: &EsdmConnection,
+    rng_type: EsdmRngType,
+) -> Result<R, EsdmError> {
+    let mut seed = R::Seed::default();
+    conn.fill_bytes(rng_type, seed.as_mut())?;
+    let rng = R::from_seed(seed.clone());
+    seed.as_mut().zeroize();
+
+    Ok(rng)
+}
+
+fn ne_u64(bytes: &[u8]) -> u64 {
+    u64::from_ne_bytes(bytes[..8].try_into().unwrap())
+}
+
+/// interprets the return value `ret` of a seed request filling `buf`, moves
+/// the seed data to the start of `buf` and wipes the bytes behind it
+pub(crate) fn seed_info(buf: &mut [u8], ret: isize, attempts: u32) -> Result<SeedInfo, EsdmError> {
+    let op = EsdmOperation::GetSeed;
+
+    if ret < 0 {
+        let code = i32::try_from(ret).unwrap_or(-libc::EINVAL);
+        // ESDM reports the needed buffer size in place of the header
+        if code == -libc::EMSGSIZE && buf.len() >= 8 {
+            let required = usize::try_from(ne_u64(buf)).unwrap_or(usize::MAX);
+            buf.zeroize();
+            if required > 0 {
+                return Err(EsdmError::BufferTooSmall { op, required });
+            }
+        }
+        return Err(EsdmError::Rpc { op, code, attempts });
+    }
+
+    let protocol_error = EsdmError::Rpc {
+        op,
+        code: -libc::EPROTO,
+        attempts,
+    };
+    let written = ret.unsigned_abs();
+    if written < SEED_HEADER_LEN || written > buf.len() {
+        buf.zeroize();
+        return Err(protocol_error);
+    }
+
+    let size = usize::try_from(ne_u64(&buf[0..8])).unwrap_or(usize::MAX);
+    let entropy_bits = ne_u64(&buf[8..16]);
+    if size < SEED_HEADER_LEN || size > written {
+        buf.zeroize();
+        return Err(protocol_error);
+    }
+
+    let len = size - SEED_HEADER_LEN;
+    buf.copy_within(SEED_HEADER_LEN..size, 0);
+    buf[len..].zeroize();
+
+    Ok(SeedInfo { len, entropy_bits })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_buf(size: u64, entropy_bits: u64, data: &[u8], buf_len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; buf_len];
+        buf[0..8].copy_from_slice(&size.to_ne_bytes());
+        buf[8..16].copy_from_slice(&entropy_bits.to_ne_bytes());
+        buf[16..16 + data.len()].copy_from_slice(data);
+        buf
+    }
+
+    #[test]
+    fn test_seed_info() {
+        let mut buf = seed_buf(20, 24, &[1, 2, 3, 4], 64);
+        let info = seed_info(&mut buf, 20, 1).unwrap();
+        assert_eq!(
+            info,
+            SeedInfo {
+                len: 4,
+                entropy_bits: 24
+            }
+        );
+        assert!(!info.is_full_entropy());
+        assert_eq!(&buf[..4], &[1, 2, 3, 4]);
+        assert!(buf[4..].iter().all(|b| *b == 0));
+
+        let mut buf = seed_buf(100, 0, &[], 64);
+        let err = seed_info(&mut buf, 64, 1).unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EPROTO));
+        assert!(buf.iter().all(|b| *b == 0));
+
+        let mut buf = seed_buf(80, 0, &[], 32);
+        let err = seed_info(&mut buf, -libc::EMSGSIZE as isize, 1).unwrap_err();
+        assert!(matches!(
+            err,
+            EsdmError::BufferTooSmall { required: 80, .. }
+        ));
+        assert_eq!(err.errno(), Some(libc::EMSGSIZE));
+
+        let err = seed_info(&mut buf, -libc::EAGAIN as isize, 3).unwrap_err();
+        assert_eq!(err.code(), Some(-libc::EAGAIN));
+        assert_eq!(err.attempts(), 3);
+    }
+
+    #[test]
+    fn test_seed_flags() {
+        let flags = SeedFlags::NON_BLOCKING | SeedFlags::FULLY_SEEDED;
+        assert_eq!(flags.bits(), 3);
+        assert!(flags.contains(SeedFlags::NON_BLOCKING));
+        assert!(!SeedFlags::BLOCKING.contains(SeedFlags::FULLY_SEEDED));
+        assert_eq!(SeedFlags::default(), SeedFlags::BLOCKING);
+    }
+}
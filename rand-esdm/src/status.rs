@@ -0,0 +1,240 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// Seeding state of the ESDM DRNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EsdmSeedLevel {
+    /// not even minimally seeded yet
+    Unseeded,
+
+    /// seeded with at least 128 bits of entropy
+    MinimallySeeded,
+
+    /// seeded with the full security strength of the DRNG
+    FullySeeded,
+}
+
+impl fmt::Display for EsdmSeedLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EsdmSeedLevel::Unseeded => f.write_str("unseeded"),
+            EsdmSeedLevel::MinimallySeeded => f.write_str("minimally seeded"),
+            EsdmSeedLevel::FullySeeded => f.write_str("fully seeded"),
+        }
+    }
+}
+
+/// Status of a per-NUMA-node DRNG instance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EsdmNodeStatus {
+    pub node: u32,
+    pub drng_name: Option<String>,
+    pub minimally_seeded: Option<bool>,
+    pub fully_seeded: Option<bool>,
+
+    /// key/value lines of this node, which are not parsed into a field
+    pub other: Vec<(String, String)>,
+}
+
+/// Parsed output of `esdm_rpcc_status`
+///
+/// Every field is optional, as the set of reported values depends on the ESDM
+/// version and its build configuration. Lines, which are not understood, are
+/// kept in `other` instead of failing the parse.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EsdmStatus {
+    pub version: Option<String>,
+    pub drng_name: Option<String>,
+    pub security_strength: Option<u32>,
+    pub drng_instances: Option<u32>,
+    pub standards_compliance: Vec<String>,
+    pub fips_mode: Option<bool>,
+    pub minimally_seeded: Option<bool>,
+    pub fully_seeded: Option<bool>,
+    pub entropy_level: Option<u32>,
+    pub nodes: Vec<EsdmNodeStatus>,
+
+    /// key/value lines, which are not parsed into a field
+    pub other: Vec<(String, String)>,
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "enabled" | "1" => Some(true),
+        "false" | "no" | "disabled" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// extracts the leading number of values like "256" or "256 bits"
+fn parse_u32(value: &str) -> Option<u32> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+/// returns the node number of section headers like "DRNG for NUMA node 1"
+fn parse_node_header(header: &str) -> Option<u32> {
+    let lower = header.to_ascii_lowercase();
+    let pos = lower.find("numa node")?;
+    parse_u32(header[pos + "numa node".len()..].trim())
+}
+
+impl EsdmStatus {
+    /// parses a status text as returned by [`crate::EsdmConnection::status_str`]
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let mut status = EsdmStatus::default();
+        let mut node: Option<EsdmNodeStatus> = None;
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            // section header
+            if value.is_empty() {
+                if let Some(n) = node.take() {
+                    status.nodes.push(n);
+                }
+                node = parse_node_header(key).map(|n| EsdmNodeStatus {
+                    node: n,
+                    ..EsdmNodeStatus::default()
+                });
+                continue;
+            }
+
+            if let Some(n) = node.as_mut() {
+                n.apply(key, value);
+            } else {
+                status.apply(key, value);
+            }
+        }
+
+        if let Some(n) = node.take() {
+            status.nodes.push(n);
+        }
+
+        status
+    }
+
+    fn apply(&mut self, key: &str, value: &str) {
+        let lower = key.to_ascii_lowercase();
+        match lower.as_str() {
+            "esdm library version" | "esdm version" => self.version = Some(value.to_string()),
+            "drng name" => self.drng_name = Some(value.to_string()),
+            "drng security strength in bits" | "esdm security strength in bits" => {
+                self.security_strength = parse_u32(value);
+            }
+            "number of drng instances" => self.drng_instances = parse_u32(value),
+            "standards compliance" => {
+                self.standards_compliance = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "esdm minimally seeded" => self.minimally_seeded = parse_bool(value),
+            "esdm fully seeded" => self.fully_seeded = parse_bool(value),
+            "esdm entropy level" => self.entropy_level = parse_u32(value),
+            "fips 140 mode" => self.fips_mode = parse_bool(value),
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// seed level derived from the seeded flags, if ESDM reported them
+    #[must_use]
+    pub fn seed_level(&self) -> Option<EsdmSeedLevel> {
+        match (self.minimally_seeded, self.fully_seeded) {
+            (_, Some(true)) => Some(EsdmSeedLevel::FullySeeded),
+            (Some(true), _) => Some(EsdmSeedLevel::MinimallySeeded),
+            (Some(false), _) | (None, Some(false)) => Some(EsdmSeedLevel::Unseeded),
+            (None, None) => None,
+        }
+    }
+}
+
+impl EsdmNodeStatus {
+    fn apply(&mut self, key: &str, value: &str) {
+        match key.to_ascii_lowercase().as_str() {
+            "drng name" => self.drng_name = Some(value.to_string()),
+            "esdm minimally seeded" | "minimally seeded" => {
+                self.minimally_seeded = parse_bool(value);
+            }
+            "esdm fully seeded" | "fully seeded" => self.fully_seeded = parse_bool(value),
+            _ => self.other.push((key.to_string(), value.to_string())),
+        }
+    }
+}
+
+impl FromStr for EsdmStatus {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(EsdmStatus::parse(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "ESDM library version: 1.2.0
+ DRNG name: ChaCha20 DRNG
+ DRNG security strength in bits: 256
+ Number of DRNG instances: 2
+ Standards compliance: NTG.1 (2011), SP800-90C
+ FIPS 140 mode: false
+ ESDM minimally seeded: true
+ ESDM fully seeded: false
+ESDM entropy level: 160
+Some future key: some value
+not a key value line
+DRNG for NUMA node 1:
+ DRNG name: ChaCha20 DRNG
+ fully seeded: true
+";
+
+    #[test]
+    fn test_parse_status() {
+        let status: EsdmStatus = STATUS.parse().unwrap();
+
+        assert_eq!(status.version.as_deref(), Some("1.2.0"));
+        assert_eq!(status.drng_name.as_deref(), Some("ChaCha20 DRNG"));
+        assert_eq!(status.security_strength, Some(256));
+        assert_eq!(status.drng_instances, Some(2));
+        assert_eq!(status.standards_compliance, ["NTG.1 (2011)", "SP800-90C"]);
+        assert_eq!(status.fips_mode, Some(false));
+        assert_eq!(status.entropy_level, Some(160));
+        assert_eq!(status.seed_level(), Some(EsdmSeedLevel::MinimallySeeded));
+        assert_eq!(
+            status.other,
+            [("Some future key".to_string(), "some value".to_string())]
+        );
+
+        assert_eq!(status.nodes.len(), 1);
+        assert_eq!(status.nodes[0].node, 1);
+        assert_eq!(status.nodes[0].fully_seeded, Some(true));
+    }
+
+    #[test]
+    fn test_parse_fips_mode() {
+        let status = EsdmStatus::parse("FIPS 140 mode: true\nFIPS 140 compliant: false\n");
+        assert_eq!(status.fips_mode, Some(true));
+        assert_eq!(
+            status.other,
+            [("FIPS 140 compliant".to_string(), "false".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_status() {
+        let status = EsdmStatus::parse("");
+        assert_eq!(status, EsdmStatus::default());
+        assert_eq!(status.seed_level(), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Process-global ESDM random number generator handles
+//!
+//! [`esdm_rng`] and [`EsdmThreadRng`] work like `rand::rng()`: the connection
+//! to ESDM is established once per process on first use and cached by every
+//! thread using it, so handles are free to create and need no setup at the
+//! call site. Connection errors are returned by the first request and retried
+//! by the next one.
+
+use std::cell::OnceCell;
+use std::sync::{Mutex, PoisonError};
+
+use rand_core::{TryCryptoRng, TryRng};
+use zeroize::Zeroizing;
+
+use crate::{EsdmConnection, EsdmError, EsdmRngType, EsdmSeedLevel};
+
+static PROCESS_CONN: Mutex<Option<EsdmConnection>> = Mutex::new(None);
+
+thread_local! {
+    static THREAD_CONN: OnceCell<EsdmConnection> = const { OnceCell::new() };
+}
+
+/// the connection shared by all threads, established on first use
+fn process_connection() -> Result<EsdmConnection, EsdmError> {
+    let mut conn = PROCESS_CONN.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*conn {
+        Some(conn) => Ok(conn.clone()),
+        None => Ok(conn.insert(EsdmConnection::new()?).clone()),
+    }
+}
+
+/// runs `f` with the connection cached by the current thread
+fn with_thread_connection<T>(
+    f: impl FnOnce(&EsdmConnection) -> Result<T, EsdmError>,
+) -> Result<T, EsdmError> {
+    THREAD_CONN.with(|cell| {
+        if let Some(conn) = cell.get() {
+            return f(conn);
+        }
+        let conn = process_connection()?;
+        f(cell.get_or_init(|| conn))
+    })
+}
+
+/// handle on the process-wide ESDM connection for fully seeded random bytes
+#[must_use]
+pub fn esdm_rng() -> EsdmThreadRng {
+    EsdmThreadRng::new(EsdmRngType::FullySeeded)
+}
+
+/// Random number generator using the process-wide ESDM connection
+///
+/// Creating and copying handles costs nothing, see [`esdm_rng`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EsdmThreadRng {
+    rng_type: EsdmRngType,
+}
+
+impl EsdmThreadRng {
+    #[must_use]
+    pub const fn new(rng_type: EsdmRngType) -> Self {
+        EsdmThreadRng { rng_type }
+    }
+
+    #[must_use]
+    pub fn rng_type(&self) -> EsdmRngType {
+        self.rng_type
+    }
+
+    /// fills `dst` like `try_fill_bytes`, returns the seed level of ESDM
+    /// before the bytes were generated
+    pub fn fill_bytes_with_seed_level(
+        &mut self,
+        dst: &mut [u8],
+    ) -> Result<EsdmSeedLevel, EsdmError> {
+        with_thread_connection(|conn| conn.fill_bytes_with_seed_level(self.rng_type, dst))
+    }
+
+    /// the process-wide connection, established if necessary
+    pub fn connection(&self) -> Result<EsdmConnection, EsdmError> {
+        with_thread_connection(|conn| Ok(conn.clone()))
+    }
+}
+
+impl TryRng for EsdmThreadRng {
+    type Error = EsdmError;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        let mut bytes = Zeroizing::new([0u8; 4]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
+
+        Ok(u32::from_ne_bytes(*bytes))
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        let mut bytes = Zeroizing::new([0u8; 8]);
+        self.try_fill_bytes(bytes.as_mut_slice())?;
+
+        Ok(u64::from_ne_bytes(*bytes))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        with_thread_connection(|conn| conn.fill_seeded_bytes(self.rng_type, dst))
+    }
+}
+
+/// same guarantees as [`EsdmRng`](crate::EsdmRng)
+impl TryCryptoRng for EsdmThreadRng {}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::EsdmOperation;
+    use crate::mock::MockEsdmServer;
+
+    #[test]
+    fn test_thread_rng() {
+        let server = MockEsdmServer::start().unwrap();
+
+        let mut rng = esdm_rng();
+        assert_ne!(rng.try_next_u64().unwrap(), rng.try_next_u64().unwrap());
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    let mut rng = EsdmThreadRng::new(EsdmRngType::PredictionResistant);
+                    for _ in 0..100 {
+                        rng.try_next_u32().unwrap();
+                    }
+                    rng.connection().unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            let conn = thread.join().unwrap();
+            assert!(conn.is_fully_seeded().unwrap());
+        }
+
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 2);
+        assert_eq!(server.calls(EsdmOperation::GetRandomBytesPr), 400);
+
+        // release the service for tests of its initialization
+        *PROCESS_CONN.lock().unwrap() = None;
+    }
+}
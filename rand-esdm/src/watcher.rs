@@ -0,0 +1,366 @@
+//! Background watcher for ESDM state changes, see [`EsdmWatcher`]
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{CancellationToken, EsdmConnection, EsdmError, EsdmNotification, EsdmSeedLevel};
+
+/// upper bound of a need-entropy wait, before checking if the watcher was stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// change of the ESDM state observed by an [`EsdmWatcher`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EsdmEvent {
+    /// the seed level changed, `previous` is `None` for the first sample
+    SeedLevelChanged {
+        previous: Option<EsdmSeedLevel>,
+        current: EsdmSeedLevel,
+    },
+
+    /// the entropy level dropped below the configured threshold
+    EntropyLevelLow { entropy_level: u32, threshold: u32 },
+
+    /// the entropy level reached the configured threshold again
+    EntropyLevelRecovered { entropy_level: u32, threshold: u32 },
+
+    /// ESDM signalled that it needs entropy, with its entropy count in bits
+    EntropyNeeded { entropy_count: u32 },
+
+    /// sampling ESDM or waiting for the notification failed
+    Error(EsdmError),
+}
+
+/// what an [`EsdmWatcher`] observes and how often
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchOptions {
+    interval: Duration,
+    entropy_threshold: Option<u32>,
+    need_entropy: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WatchOptions {
+    /// samples every 5 s, waits for need-entropy notifications, no entropy
+    /// level threshold
+    #[must_use]
+    pub const fn new() -> Self {
+        WatchOptions {
+            interval: Duration::from_secs(5),
+            entropy_threshold: None,
+            need_entropy: true,
+        }
+    }
+
+    /// time between two samples of the seed and entropy level
+    #[must_use]
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// reports the entropy level dropping below and recovering to `threshold` bits
+    #[must_use]
+    pub const fn with_entropy_threshold(mut self, threshold: u32) -> Self {
+        self.entropy_threshold = Some(threshold);
+        self
+    }
+
+    /// reports need-entropy notifications of the aux client
+    #[must_use]
+    pub const fn with_need_entropy(mut self, need_entropy: bool) -> Self {
+        self.need_entropy = need_entropy;
+        self
+    }
+
+    #[must_use]
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    #[must_use]
+    pub const fn entropy_threshold(&self) -> Option<u32> {
+        self.entropy_threshold
+    }
+
+    #[must_use]
+    pub const fn need_entropy(&self) -> bool {
+        self.need_entropy
+    }
+}
+
+/// last observed state, to report transitions only
+#[derive(Debug, Default)]
+struct Observed {
+    seed_level: Option<EsdmSeedLevel>,
+    entropy_low: bool,
+    failing: bool,
+}
+
+struct Watch {
+    conn: EsdmConnection,
+    notification: Option<EsdmNotification>,
+    options: WatchOptions,
+    observed: Observed,
+}
+
+impl Watch {
+    fn sample(&mut self, emit: &mut impl FnMut(EsdmEvent)) {
+        let sample = self
+            .conn
+            .seed_level()
+            .and_then(|seed_level| Ok((seed_level, self.conn.entropy_level()?)));
+        let (seed_level, entropy_level) = match sample {
+            Ok(sample) => sample,
+            Err(err) => {
+                if !self.observed.failing {
+                    self.observed.failing = true;
+                    emit(EsdmEvent::Error(err));
+                }
+                return;
+            }
+        };
+        self.observed.failing = false;
+
+        if self.observed.seed_level != Some(seed_level) {
+            emit(EsdmEvent::SeedLevelChanged {
+                previous: self.observed.seed_level.replace(seed_level),
+                current: seed_level,
+            });
+        }
+
+        if let Some(threshold) = self.options.entropy_threshold {
+            let entropy_low = entropy_level < threshold;
+            if entropy_low && !self.observed.entropy_low {
+                emit(EsdmEvent::EntropyLevelLow {
+                    entropy_level,
+                    threshold,
+                });
+            } else if !entropy_low && self.observed.entropy_low {
+                emit(EsdmEvent::EntropyLevelRecovered {
+                    entropy_level,
+                    threshold,
+                });
+            }
+            self.observed.entropy_low = entropy_low;
+        }
+    }
+
+    /// waits until the next sample is due, reports a need-entropy notification
+    /// in the meantime, returns false once stopped
+    fn wait(&self, stop: &CancellationToken, emit: &mut impl FnMut(EsdmEvent)) -> bool {
+        let next_sample = Instant::now() + self.options.interval;
+        let mut notified = false;
+
+        loop {
+            let remaining = next_sample.saturating_duration_since(Instant::now());
+            if stop.is_cancelled() {
+                return false;
+            }
+            if remaining.is_zero() {
+                return true;
+            }
+
+            match &self.notification {
+                Some(notification) if !notified => {
+                    match notification
+                        .wait_for_entropy_needed_timeout(remaining.min(STOP_POLL_INTERVAL))
+                    {
+                        Err(EsdmError::Timeout { .. }) => {}
+                        Ok(entropy_count) => {
+                            notified = true;
+                            emit(EsdmEvent::EntropyNeeded { entropy_count });
+                        }
+                        Err(err) => {
+                            notified = true;
+                            emit(EsdmEvent::Error(err));
+                        }
+                    }
+                }
+                _ => {
+                    if stop.sleep(remaining) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Background thread reporting ESDM state changes
+///
+/// The watcher samples the seed level and entropy level of ESDM on a
+/// dedicated thread and reports changes as [`EsdmEvent`]s, either to a callback
+/// or through a channel. In between samples it waits for the need-entropy
+/// notification of the aux client, so that [`EsdmEvent::EntropyNeeded`] is
+/// reported without waiting for the next sample (at most once per interval).
+///
+/// The first sample reports the current seed level (with no previous level)
+/// and, if configured, a low entropy level. Afterwards only transitions are
+/// reported. A failing sample is reported once as [`EsdmEvent::Error`] until
+/// ESDM can be sampled again.
+///
+/// The thread ends when the watcher is stopped or dropped.
+#[derive(Debug)]
+pub struct EsdmWatcher {
+    stop: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EsdmWatcher {
+    /// starts watching ESDM, `callback` is called on the watcher thread
+    pub fn spawn(
+        options: WatchOptions,
+        mut callback: impl FnMut(EsdmEvent) + Send + 'static,
+    ) -> Result<Self, EsdmError> {
+        let mut watch = Watch {
+            conn: EsdmConnection::new()?,
+            notification: if options.need_entropy {
+                Some(EsdmNotification::try_new()?)
+            } else {
+                None
+            },
+            options,
+            observed: Observed::default(),
+        };
+
+        let stop = CancellationToken::new();
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            loop {
+                watch.sample(&mut callback);
+                if !watch.wait(&thread_stop, &mut callback) {
+                    break;
+                }
+            }
+        });
+
+        Ok(EsdmWatcher {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// starts watching ESDM, events are sent to the returned receiver
+    pub fn channel(options: WatchOptions) -> Result<(Self, mpsc::Receiver<EsdmEvent>), EsdmError> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = Self::spawn(options, move |event| {
+            // the receiver may be gone before the watcher is stopped
+            let _ = tx.send(event);
+        })?;
+
+        Ok((watcher, rx))
+    }
+
+    /// stops watching and waits for the watcher thread to end
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.cancel();
+        if let Some(thread) = self.thread.take() {
+            // a panicking callback already ended the thread
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for EsdmWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEsdmServer;
+
+    fn next_event(rx: &mpsc::Receiver<EsdmEvent>) -> EsdmEvent {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_watcher_transitions() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_seed_level(EsdmSeedLevel::MinimallySeeded);
+        server.set_entropy_level(64);
+
+        let options = WatchOptions::new()
+            .with_interval(Duration::from_millis(20))
+            .with_entropy_threshold(128)
+            .with_need_entropy(false);
+        let (watcher, rx) = EsdmWatcher::channel(options).unwrap();
+
+        assert!(matches!(
+            next_event(&rx),
+            EsdmEvent::SeedLevelChanged {
+                previous: None,
+                current: EsdmSeedLevel::MinimallySeeded
+            }
+        ));
+        assert!(matches!(
+            next_event(&rx),
+            EsdmEvent::EntropyLevelLow {
+                entropy_level: 64,
+                threshold: 128
+            }
+        ));
+
+        server.set_entropy_level(256);
+        server.set_seed_level(EsdmSeedLevel::FullySeeded);
+        let mut events = [next_event(&rx), next_event(&rx)];
+        events.sort_by_key(|event| matches!(event, EsdmEvent::EntropyLevelRecovered { .. }));
+        assert!(matches!(
+            events[0],
+            EsdmEvent::SeedLevelChanged {
+                previous: Some(EsdmSeedLevel::MinimallySeeded),
+                current: EsdmSeedLevel::FullySeeded
+            }
+        ));
+        assert!(matches!(
+            events[1],
+            EsdmEvent::EntropyLevelRecovered {
+                entropy_level: 256,
+                ..
+            }
+        ));
+
+        // failures are reported once per outage
+        server.set_reachable(false);
+        assert!(matches!(next_event(&rx), EsdmEvent::Error(_)));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(rx.try_recv().is_err());
+
+        watcher.stop();
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_secs(1)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn test_watcher_need_entropy() {
+        let server = MockEsdmServer::start().unwrap();
+        server.set_write_wakeup_thresh(128);
+        server.set_entropy_count(64);
+
+        let (tx, rx) = mpsc::channel();
+        let watcher = EsdmWatcher::spawn(WatchOptions::new(), move |event| {
+            if let EsdmEvent::EntropyNeeded { entropy_count } = event {
+                tx.send(entropy_count).unwrap();
+            }
+        })
+        .unwrap();
+
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 64);
+        drop(watcher);
+    }
+}
@@ -0,0 +1,35 @@
+//! Expands `register_getrandom_backend!` like a binary crate does
+//!
+//! With `RUSTFLAGS='--cfg getrandom_backend="custom"'`, `getrandom::fill` is
+//! routed through the registered backend, otherwise the backend function is
+//! called directly.
+
+use rand_esdm::getrandom;
+use rand_esdm::mock::MockEsdmServer;
+use rand_esdm::{EsdmOperation, FallbackPolicy, esdm_set_getrandom_fallback};
+
+rand_esdm::register_getrandom_backend!();
+
+/// signature `getrandom` declares for its custom backend
+type CustomBackend = unsafe extern "Rust" fn(*mut u8, usize) -> Result<(), getrandom::Error>;
+
+const BACKEND: CustomBackend = __getrandom_v03_custom;
+
+#[test]
+fn test_register_getrandom_backend() {
+    let server = MockEsdmServer::start().unwrap();
+    esdm_set_getrandom_fallback(FallbackPolicy::Never);
+
+    let mut buf = [0u8; 64];
+    if cfg!(getrandom_backend = "custom") {
+        getrandom::fill(&mut buf).unwrap();
+    } else {
+        unsafe { BACKEND(buf.as_mut_ptr(), buf.len()) }.unwrap();
+    }
+    assert!(buf.iter().any(|b| *b != 0));
+    assert_eq!(server.calls(EsdmOperation::GetRandomBytesFull), 1);
+
+    server.fail_always(EsdmOperation::GetRandomBytesFull, -libc::EPERM);
+    let err = unsafe { BACKEND(buf.as_mut_ptr(), buf.len()) }.unwrap_err();
+    assert_eq!(err, getrandom::Error::new_custom(1));
+}